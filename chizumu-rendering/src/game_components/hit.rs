@@ -19,11 +19,21 @@ use chizumu_gpu::{
 };
 use nalgebra::{Matrix4, Vector3, Vector4};
 
-use crate::game_components::HitObject;
+use crate::{
+    game_components::{HitObject, HitObjectFade},
+    HIT_AREA_Z_START,
+};
 
 pub const TAP_Z_RANGE: f32 = 0.14;
 const MAX_HIT_OBJECT_INSTANCE_COUNT: usize = 2048;
 
+/// Lifetimes of effects in seconds.
+const HIT_EFFECT_DURATION: f32 = 0.25;
+const JUDGEMENT_LINE_FLASH_DURATION: f32 = 0.15;
+
+/// Width of the runner marker on the x axis.
+const RUNNER_MARKER_WIDTH: f32 = 0.1;
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 struct InstanceData {
@@ -31,6 +41,17 @@ struct InstanceData {
     color: Vector4<f32>,
 }
 
+/// Short lived instance drawn at the hit area, fades out over its lifetime.
+struct HitEffect {
+    x_offset: f32,
+    x_scale: f32,
+    z_scale: f32,
+    color: Vector4<f32>,
+    duration: f32,
+    /// In seconds.
+    remaining: f32,
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 struct RunnerData {
@@ -50,8 +71,15 @@ pub(crate) struct HitRenderer {
 
     hit_objects: Vec<HitObject>,
     hit_objects_instance_data: Vec<InstanceData>,
-
-    runner_position: f32,
+    /// Hidden objects are kept in the instance data with zero opacity, e.g. notes that were hit.
+    hit_objects_hidden: Vec<bool>,
+    fade: HitObjectFade,
+    /// Drawn after the hit objects.
+    effects: Vec<HitEffect>,
+    /// Lateral position and color of the runner, drawn at the hit area after the effects.
+    runner_marker: Option<(f32, Vector4<f32>)>,
+    /// Instance data changed and needs to be written to the GPU.
+    instance_data_dirty: bool,
 
     descriptor_sets: [DescriptorSet; MAX_FRAMES],
     graphics_pipeline: Pipeline,
@@ -108,26 +136,169 @@ impl HitRenderer {
             current_instance_count: 0,
             hit_objects: Vec::new(),
             hit_objects_instance_data: Vec::new(),
-            runner_position: 0.0,
+            hit_objects_hidden: Vec::new(),
+            fade: HitObjectFade::default(),
+            effects: Vec::new(),
+            runner_marker: None,
+            instance_data_dirty: false,
         })
     }
 
-    pub(crate) fn update(&self) -> Result<()> {
+    pub(crate) fn update(&mut self, frame_dt: f32, runner_position: f32) -> Result<()> {
         let runnner_data = RunnerData {
-            model: Matrix4::new_translation(&Vector3::new(0.0, 0.0, -self.runner_position)),
+            model: Matrix4::new_translation(&Vector3::new(0.0, 0.0, -runner_position)),
         };
         self.buffer_uniform_runner_data
             .write_data(&[runnner_data])?;
 
+        let hit_area_position = runner_position + HIT_AREA_Z_START;
+
+        if self.fade.is_enabled() {
+            for ((object, instance_data), hidden) in self
+                .hit_objects
+                .iter()
+                .zip(self.hit_objects_instance_data.iter_mut())
+                .zip(&self.hit_objects_hidden)
+            {
+                if !hidden {
+                    instance_data.color.w = self.fade.alpha(object.z_offset - hit_area_position);
+                }
+            }
+            self.instance_data_dirty = true;
+        }
+
+        if !self.effects.is_empty() {
+            for effect in self.effects.iter_mut() {
+                effect.remaining -= frame_dt;
+            }
+            self.effects.retain(|effect| effect.remaining > 0.0);
+            // Also written once all effects ended so they stop being drawn.
+            self.instance_data_dirty = true;
+        }
+
+        // The marker moves along with the hit area.
+        if self.runner_marker.is_some() {
+            self.instance_data_dirty = true;
+        }
+
+        if self.instance_data_dirty {
+            self.write_instance_data(hit_area_position)?;
+            self.instance_data_dirty = false;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the hit objects followed by the effects and the runner marker positioned at the hit area.
+    fn write_instance_data(&mut self, hit_area_position: f32) -> Result<()> {
+        let runner_marker_instance_data =
+            self.runner_marker
+                .map(|(lateral_position, color)| InstanceData {
+                    model: Matrix4::new_translation(&Vector3::new(
+                        lateral_position - RUNNER_MARKER_WIDTH / 2.0,
+                        0.0,
+                        hit_area_position,
+                    )) * Matrix4::new_nonuniform_scaling(&Vector3::new(
+                        RUNNER_MARKER_WIDTH / 2.0,
+                        1.0,
+                        0.5,
+                    )),
+                    color,
+                });
+
+        let effect_capacity = MAX_HIT_OBJECT_INSTANCE_COUNT
+            .saturating_sub(self.hit_objects_instance_data.len())
+            .saturating_sub(runner_marker_instance_data.iter().count());
+        let effects_instance_data = self.effects.iter().take(effect_capacity).map(|effect| {
+            let progress = 1.0 - effect.remaining / effect.duration;
+            InstanceData {
+                model: Matrix4::new_translation(&Vector3::new(
+                    effect.x_offset,
+                    0.0,
+                    hit_area_position,
+                )) * Matrix4::new_nonuniform_scaling(&Vector3::new(
+                    effect.x_scale,
+                    1.0 + 2.0 * progress,
+                    effect.z_scale,
+                )),
+                color: Vector4::new(
+                    effect.color.x,
+                    effect.color.y,
+                    effect.color.z,
+                    effect.color.w * (1.0 - progress),
+                ),
+            }
+        });
+
+        let instance_data = self
+            .hit_objects_instance_data
+            .iter()
+            .copied()
+            .chain(effects_instance_data)
+            .chain(runner_marker_instance_data)
+            .collect::<Vec<_>>();
+        self.current_first_instance = 0;
+        self.current_instance_count = instance_data.len() as _;
+        self.buffer_instance_data_hit_objects
+            .write_data(&instance_data)?;
+
         Ok(())
     }
 
-    pub(crate) fn advance_runner(&mut self, advance_amount: f32) {
-        self.runner_position += advance_amount;
+    pub(crate) fn hide_hit_object(&mut self, index: usize) {
+        if let Some(instance_data) = self.hit_objects_instance_data.get_mut(index) {
+            instance_data.color.w = 0.0;
+            self.hit_objects_hidden[index] = true;
+            self.instance_data_dirty = true;
+        }
+    }
+
+    /// Opacity is kept as is, it is controlled by hiding and fading.
+    pub(crate) fn set_hit_object_color(&mut self, index: usize, color: Vector3<f32>) {
+        if let Some(instance_data) = self.hit_objects_instance_data.get_mut(index) {
+            instance_data.color.x = color.x;
+            instance_data.color.y = color.y;
+            instance_data.color.z = color.z;
+            self.instance_data_dirty = true;
+        }
+    }
+
+    /// Spawns a burst at the hit area in the lane of the hit object.
+    pub(crate) fn spawn_hit_effect(&mut self, index: usize, color: Vector4<f32>) {
+        if let Some(object) = self.hit_objects.get(index) {
+            self.effects.push(HitEffect {
+                x_offset: object.x_offset,
+                x_scale: object.x_scale,
+                z_scale: 1.0,
+                color,
+                duration: HIT_EFFECT_DURATION,
+                remaining: HIT_EFFECT_DURATION,
+            });
+        }
+    }
+
+    /// `None` hides the marker.
+    pub(crate) fn set_runner_marker(&mut self, runner_marker: Option<(f32, Vector4<f32>)>) {
+        if self.runner_marker != runner_marker {
+            self.runner_marker = runner_marker;
+            self.instance_data_dirty = true;
+        }
+    }
+
+    /// Flashes a thin line across the whole lane at the hit area.
+    pub(crate) fn flash_judgement_line(&mut self, color: Vector4<f32>) {
+        self.effects.push(HitEffect {
+            x_offset: -1.0,
+            x_scale: 1.0,
+            z_scale: 0.25,
+            color,
+            duration: JUDGEMENT_LINE_FLASH_DURATION,
+            remaining: JUDGEMENT_LINE_FLASH_DURATION,
+        });
     }
 
-    pub(crate) fn get_runner_position(&self) -> f32 {
-        self.runner_position
+    pub(crate) fn set_fade(&mut self, fade: HitObjectFade) {
+        self.fade = fade;
     }
 
     pub(crate) fn add_hit_objects(&mut self, hit_objects: &[HitObject]) {
@@ -149,6 +320,7 @@ impl HitRenderer {
             };
             self.hit_objects_instance_data.push(instance_data);
             self.hit_objects.push(object.clone());
+            self.hit_objects_hidden.push(false);
         }
 
         // XXX: More work required on deciding what is drawn per frame based on this data.
@@ -160,6 +332,16 @@ impl HitRenderer {
             .unwrap();
     }
 
+    pub(crate) fn clear_hit_objects(&mut self) {
+        self.hit_objects.clear();
+        self.hit_objects_instance_data.clear();
+        self.hit_objects_hidden.clear();
+        self.effects.clear();
+        self.runner_marker = None;
+        self.current_first_instance = 0;
+        self.current_instance_count = 0;
+    }
+
     pub(crate) fn write_render_commands(&self, command_buffer: &CommandBuffer, current_frame: u64) {
         // self.write_gpu_resources_hit_objects().unwrap();
 
@@ -301,8 +483,15 @@ impl HitRenderer {
             .stride(12)
             .input_rate(vk::VertexInputRate::VERTEX)];
 
+        // Blended so objects can fade in and out.
         let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
-            .blend_enable(false)
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD)
             .color_write_mask(vk::ColorComponentFlags::RGBA);
 
         let rasterization_state = PipelineRasterizationState::new()
@@ -27,6 +27,35 @@ impl HitObject {
     }
 }
 
+/// Runner distances from the hit area over which hit objects are shown.
+#[derive(Clone, Copy, Default)]
+pub struct HitObjectFade {
+    /// Objects closer to the hit area than this are hidden.
+    pub hidden_distance: Option<f32>,
+    /// Objects further away from the hit area than this are hidden.
+    pub sudden_distance: Option<f32>,
+}
+
+impl HitObjectFade {
+    /// Runner distance over which objects fade in or out.
+    const FADE_LENGTH: f32 = 1.0;
+
+    /// Opacity of an object `distance` ahead of the hit area.
+    pub fn alpha(&self, distance: f32) -> f32 {
+        let hidden_alpha = self.hidden_distance.map_or(1.0, |hidden_distance| {
+            ((distance - hidden_distance) / Self::FADE_LENGTH).clamp(0.0, 1.0)
+        });
+        let sudden_alpha = self.sudden_distance.map_or(1.0, |sudden_distance| {
+            ((sudden_distance - distance) / Self::FADE_LENGTH).clamp(0.0, 1.0)
+        });
+        hidden_alpha * sudden_alpha
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.hidden_distance.is_some() || self.sudden_distance.is_some()
+    }
+}
+
 #[derive(Clone)]
 pub struct DynamicPlanePlatform {
     runner_position_start: f32,
@@ -204,6 +204,8 @@ impl SingleMeshTypePlatformRenderer {
         self.buffer_indices.write_data(&indices)?;
 
         self.platforms = platforms;
+        // Platforms may be replaced mid-run, e.g. when another chart is loaded.
+        self.draw_range = DrawRange::new();
         Ok(platform_instances_data)
     }
 
@@ -12,21 +12,32 @@ fn to_xz(v: Vector2<f32>) -> Vector3<f32> {
     Vector3::new(v.x, 0.0, v.y)
 }
 
-/// Creates points for a curve on the xz 3D axis given cubic bezier parameters.
-fn cubic_bezier_curve_points_xz(
+/// Number of bisection steps used to find a curve's x position at a z position.
+const BEZIER_Z_SEARCH_STEPS: usize = 24;
+
+fn cubic_bezier_curve(
     v0: Vector2<f32>,
     v1: Vector2<f32>,
     control_points: (Vector2<f32>, Vector2<f32>),
-    subdivisions: usize,
-) -> Vec<Vector3<f32>> {
-    let curve = Curve::from_points(
+) -> Curve {
+    Curve::from_points(
         Coord2(v0.x as _, v0.y as _),
         (
             Coord2(control_points.0.x as _, control_points.0.y as _),
             Coord2(control_points.1.x as _, control_points.1.y as _),
         ),
         Coord2(v1.x as _, v1.y as _),
-    );
+    )
+}
+
+/// Creates points for a curve on the xz 3D axis given cubic bezier parameters.
+fn cubic_bezier_curve_points_xz(
+    v0: Vector2<f32>,
+    v1: Vector2<f32>,
+    control_points: (Vector2<f32>, Vector2<f32>),
+    subdivisions: usize,
+) -> Vec<Vector3<f32>> {
+    let curve = cubic_bezier_curve(v0, v1, control_points);
     (0..subdivisions + 1)
         .step_by(1)
         .map(|t| curve.point_at_pos(t as f64 / subdivisions as f64))
@@ -34,6 +45,29 @@ fn cubic_bezier_curve_points_xz(
         .collect::<Vec<_>>()
 }
 
+/// X position of the cubic bezier curve used by the bezier planes where it crosses `z`.
+/// The curve is assumed to only move forward on the z axis, which holds for platform edges.
+pub fn cubic_bezier_x_at_z(
+    v0: Vector2<f32>,
+    v1: Vector2<f32>,
+    control_points: (Vector2<f32>, Vector2<f32>),
+    z: f32,
+) -> f32 {
+    let curve = cubic_bezier_curve(v0, v1, control_points);
+
+    let (mut t_min, mut t_max) = (0.0, 1.0);
+    for _ in 0..BEZIER_Z_SEARCH_STEPS {
+        let t = (t_min + t_max) / 2.0;
+        if curve.point_at_pos(t).1 < z as f64 {
+            t_min = t;
+        } else {
+            t_max = t;
+        }
+    }
+
+    curve.point_at_pos((t_min + t_max) / 2.0).0 as f32
+}
+
 impl Plane {
     /// Plane on the xz axis.
     pub fn quad(v0: Vector2<f32>, v1: Vector2<f32>, v2: Vector2<f32>, v3: Vector2<f32>) -> Self {
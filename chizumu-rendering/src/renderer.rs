@@ -14,14 +14,16 @@ use chizumu_gpu::{
     },
     shader::{ShaderModuleDescriptor, ShaderStage},
 };
-use nalgebra::{Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector2, Vector3};
+use nalgebra::{
+    Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector2, Vector3, Vector4,
+};
 
 use crate::{
     game_components::{
         hit::HitRenderer,
         lane::{self, LaneRenderer},
         platform::PlatformRenderer,
-        DynamicPlanePlatform, HitObject, PlatformObject,
+        DynamicPlanePlatform, HitObject, HitObjectFade, PlatformObject,
     },
     line::LineRenderer,
     mesh::plane::Plane,
@@ -112,12 +114,11 @@ impl Renderer {
         Ok(())
     }
 
-    pub fn update(&mut self, frame_dt: f32, runner_dp: f32) -> Result<()> {
-        self.hit_renderer.update()?;
-        self.hit_renderer.advance_runner(runner_dp);
-
-        self.runner_position += runner_dp;
+    /// `runner_position` - absolute distance covered by the runner, all game components are positioned from it.
+    pub fn update(&mut self, frame_dt: f32, runner_position: f32) -> Result<()> {
+        self.runner_position = runner_position;
 
+        self.hit_renderer.update(frame_dt, self.runner_position)?;
         self.platform_renderer
             .update_with_runner_position(self.runner_position);
 
@@ -125,19 +126,8 @@ impl Renderer {
     }
 
     fn update_scene_constants(&self) -> Result<()> {
-        // XXX TODO: Need to find good parameters for this
-        let eye = Point3::new(0.0, -1.54, 0.2);
-        let target = Point3::new(0.0, 0.7, 3.0);
-
-        let view = Isometry3::look_at_rh(&eye, &target, &Vector3::y());
-        let projection = Perspective3::new(1920.0 / 1200.0, 3.14 / 3.0, 0.01, 1000.0);
-        let view_projection = projection.into_inner()
-            * view.to_homogeneous()
-            // XXX: Use view and projection matrices that fit accordingly to the vulkan coord system. (?)
-            * Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0));
-
         let scene_constants = SceneConstantsGpuData {
-            view_projection,
+            view_projection: camera_view_projection(),
             viewport: Vector2::new(1920, 1200),
             runner: Matrix4::new_translation(&Vector3::new(0.0, 0.0, -self.runner_position)),
             _pad0: Vector2::identity(),
@@ -148,6 +138,15 @@ impl Renderer {
         Ok(())
     }
 
+    /// Mapping of screen positions onto the playfield through the current camera, e.g. for touch input.
+    pub fn screen_to_playfield(&self) -> ScreenToPlayfield {
+        ScreenToPlayfield {
+            inverse_view_projection: camera_view_projection()
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity),
+        }
+    }
+
     pub fn set_platform_objects(&mut self, platform_objects: Vec<PlatformObject>) -> Result<()> {
         self.platform_renderer
             .set_platforms_objects(platform_objects)?;
@@ -158,4 +157,88 @@ impl Renderer {
     pub fn add_hit_objects(&mut self, hit_objects: &[HitObject]) {
         self.hit_renderer.add_hit_objects(hit_objects);
     }
+
+    /// Removes all hit and platform objects, e.g. before another chart is loaded.
+    pub fn clear_game_objects(&mut self) -> Result<()> {
+        self.hit_renderer.clear_hit_objects();
+        self.platform_renderer.set_platforms_objects(Vec::new())?;
+
+        Ok(())
+    }
+
+    /// Hit objects are indexed in the order they were added.
+    pub fn hide_hit_object(&mut self, index: usize) {
+        self.hit_renderer.hide_hit_object(index);
+    }
+
+    pub fn set_hit_object_color(&mut self, index: usize, color: Vector3<f32>) {
+        self.hit_renderer.set_hit_object_color(index, color);
+    }
+
+    pub fn spawn_hit_effect(&mut self, index: usize, color: Vector4<f32>) {
+        self.hit_renderer.spawn_hit_effect(index, color);
+    }
+
+    pub fn flash_judgement_line(&mut self, color: Vector4<f32>) {
+        self.hit_renderer.flash_judgement_line(color);
+    }
+
+    /// Marks the runner's lateral position at the hit area, `None` hides the marker.
+    pub fn set_runner_marker(&mut self, runner_marker: Option<(f32, Vector4<f32>)>) {
+        self.hit_renderer.set_runner_marker(runner_marker);
+    }
+
+    /// Fades hit objects by their distance from the hit area, e.g. for hidden and sudden modifiers.
+    pub fn set_hit_object_fade(&mut self, fade: HitObjectFade) {
+        self.hit_renderer.set_fade(fade);
+    }
+}
+
+fn camera_view_projection() -> Matrix4<f32> {
+    // XXX TODO: Need to find good parameters for this
+    let eye = Point3::new(0.0, -1.54, 0.2);
+    let target = Point3::new(0.0, 0.7, 3.0);
+
+    let view = Isometry3::look_at_rh(&eye, &target, &Vector3::y());
+    let projection = Perspective3::new(1920.0 / 1200.0, 3.14 / 3.0, 0.01, 1000.0);
+    projection.into_inner()
+        * view.to_homogeneous()
+        // XXX: Use view and projection matrices that fit accordingly to the vulkan coord system. (?)
+        * Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0))
+}
+
+/// Unprojects screen positions onto the plane game objects are placed on.
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenToPlayfield {
+    inverse_view_projection: Matrix4<f32>,
+}
+
+impl ScreenToPlayfield {
+    /// `screen_position` - in [0, 1] from the top left of the window, the scene is stretched over the whole window.
+    /// Returns the x and z of the point on the playfield, z relative to the runner. `None` if the position is
+    /// above the horizon.
+    pub fn playfield_position(&self, screen_position: Vector2<f32>) -> Option<Vector2<f32>> {
+        // Vulkan's y axis points down, the same as the window's.
+        let ndc_x = screen_position.x * 2.0 - 1.0;
+        let ndc_y = screen_position.y * 2.0 - 1.0;
+
+        let near = self
+            .inverse_view_projection
+            .transform_point(&Point3::new(ndc_x, ndc_y, -1.0));
+        let far = self
+            .inverse_view_projection
+            .transform_point(&Point3::new(ndc_x, ndc_y, 1.0));
+
+        let direction = far - near;
+        if direction.y.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = -near.y / direction.y;
+        if t < 0.0 {
+            return None;
+        }
+
+        let position = near + direction * t;
+        Some(Vector2::new(position.x, position.z))
+    }
 }
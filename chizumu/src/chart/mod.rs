@@ -4,8 +4,13 @@
  */
 use anyhow::{anyhow, Result};
 
+pub mod modifiers;
 pub mod parse;
 pub mod runtime;
+pub mod tempo;
+
+/// Number of cells notes are placed on across the playfield.
+pub const NUM_CELLS: u32 = 10;
 
 #[derive(Debug, Clone)]
 struct MusicPosition {
@@ -34,10 +39,18 @@ pub struct ChartInfo {
     pub music_file_path: String,
     /// Starting offset in seconds before the first measure.
     music_starting_offset: f32,
+
+    /// Name of the chart's difficulty, empty if the chart does not give one.
+    pub difficulty: String,
+    /// Hitsound pack the chart is played with, empty to use the one picked in the settings.
+    pub hitsound_pack: String,
+
+    /// Hash of the chart file contents, identifies the exact chart a replay or score was made on.
+    pub chart_hash: u64,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum NoteInputType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteInputType {
     Tap1,
     Tap2,
     Tap3,
@@ -77,6 +90,9 @@ struct Note {
     /// cells the note covers.
     cell: u32,
     width: u32,
+
+    /// End of the note for notes that need to be held.
+    end_music_position: Option<MusicPosition>,
 }
 
 #[derive(Debug, Clone)]
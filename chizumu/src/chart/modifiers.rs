@@ -0,0 +1,144 @@
+/*!
+ * Gameplay modifiers applied when building the runtime chart.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use super::{CommonPlatformParameters, Note, Platform, PlatformBezierControlPoint, NUM_CELLS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChartModifiers {
+    /// Flips notes and platforms horizontally.
+    pub mirror: bool,
+    /// Seed of the lane randomization, lanes are played as written if `None`.
+    pub random_seed: Option<u64>,
+    /// Notes closer to the hit area than this runner distance fade out.
+    pub hidden_distance: Option<f32>,
+    /// Notes further away from the hit area than this runner distance are not shown.
+    pub sudden_distance: Option<f32>,
+    /// Multiplier of the runner speed, only changes how far apart notes are drawn.
+    pub speed_multiplier: f32,
+}
+
+impl Default for ChartModifiers {
+    fn default() -> Self {
+        Self {
+            mirror: false,
+            random_seed: None,
+            hidden_distance: None,
+            sudden_distance: None,
+            speed_multiplier: 1.0,
+        }
+    }
+}
+
+impl ChartModifiers {
+    pub(super) fn apply_to_notes(&self, notes: &mut [Note]) {
+        if let Some(seed) = self.random_seed {
+            // Notes covering several cells can not be moved to another lane without breaking their shape,
+            // only single cell notes are shuffled.
+            let lanes = shuffled_lanes(seed);
+            for note in notes.iter_mut().filter(|note| note.width == 1) {
+                note.cell = lanes[note.cell as usize % lanes.len()];
+            }
+        }
+
+        if self.mirror {
+            for note in notes.iter_mut() {
+                note.cell = NUM_CELLS.saturating_sub(note.cell + note.width);
+            }
+        }
+    }
+
+    pub(super) fn apply_to_platform(&self, platform: &Platform) -> Platform {
+        if self.mirror {
+            mirror_platform(platform)
+        } else {
+            platform.clone()
+        }
+    }
+}
+
+/// SplitMix64, a small generator is enough to make seeded shuffles reproducible across platforms.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Lane each cell is moved to.
+fn shuffled_lanes(seed: u64) -> Vec<u32> {
+    let mut rng = SplitMix64 { state: seed };
+    let mut lanes = (0..NUM_CELLS).collect::<Vec<_>>();
+    for i in (1..lanes.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        lanes.swap(i, j);
+    }
+    lanes
+}
+
+/// Platforms are placed around x = 0, mirroring negates placements and swaps the left and right sides.
+fn mirror_params(params: &CommonPlatformParameters) -> CommonPlatformParameters {
+    CommonPlatformParameters {
+        start_placement_offset: -(params.start_placement_offset + params.start_width),
+        end_placement_offset: -(params.end_placement_offset + params.end_width),
+        ..params.clone()
+    }
+}
+
+/// `offset` - shift applied after negating the placement.
+fn mirror_control_point(
+    control_point: &PlatformBezierControlPoint,
+    offset: f32,
+) -> PlatformBezierControlPoint {
+    PlatformBezierControlPoint {
+        music_position: control_point.music_position.clone(),
+        placement_offset: -control_point.placement_offset + offset,
+    }
+}
+
+fn mirror_platform(platform: &Platform) -> Platform {
+    let mirror_pair = |points: &(PlatformBezierControlPoint, PlatformBezierControlPoint),
+                       offset: f32| {
+        (
+            mirror_control_point(&points.0, offset),
+            mirror_control_point(&points.1, offset),
+        )
+    };
+
+    let mut platform = platform.clone();
+    match &mut platform {
+        Platform::DynamicQuad(platform) => {
+            platform.params = mirror_params(&platform.params);
+        }
+        Platform::DoubleSidedBezier(platform) => {
+            platform.params = mirror_params(&platform.params);
+            let left_side_control_points = mirror_pair(&platform.right_side_control_points, 0.0);
+            platform.right_side_control_points =
+                mirror_pair(&platform.left_side_control_points, 0.0);
+            platform.left_side_control_points = left_side_control_points;
+        }
+        Platform::DoubleSidedParallelBezier(platform) => {
+            // Placements are of the left side, the right side is `width` away from it.
+            let width = platform.width;
+            platform.params.start_placement_offset =
+                -(platform.params.start_placement_offset + width);
+            platform.params.end_placement_offset = -(platform.params.end_placement_offset + width);
+            platform.control_points = mirror_pair(&platform.control_points, -width);
+        }
+        Platform::SingleSidedBezier(platform) => {
+            platform.params = mirror_params(&platform.params);
+            platform.control_points = mirror_pair(&platform.control_points, 0.0);
+            platform.is_left = !platform.is_left;
+        }
+    }
+    platform
+}
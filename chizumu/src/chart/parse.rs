@@ -1,10 +1,11 @@
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::str::FromStr;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
-use super::{runtime::RuntimeChart, *};
+use super::{modifiers::ChartModifiers, runtime::RuntimeChart, *};
 
 const COMMENT_STR: &str = "//";
 
@@ -18,6 +19,8 @@ enum Tag {
     PlayfieldChanges,
     MusicFilePath,
     MusicStartingOffset,
+    Difficulty,
+    HitsoundPack,
 }
 
 impl TryFrom<&str> for Tag {
@@ -34,11 +37,23 @@ impl TryFrom<&str> for Tag {
             "PLATFORMS" => Ok(Tag::Platforms),
             "MUSIC_FILE_PATH" => Ok(Tag::MusicFilePath),
             "MUSIC_STARTING_OFFSET" => Ok(Tag::MusicStartingOffset),
+            "DIFFICULTY" => Ok(Tag::Difficulty),
+            "HITSOUND_PACK" => Ok(Tag::HitsoundPack),
             _ => Err(anyhow!("Invalid string for Tag conversion: {}", s)),
         }
     }
 }
 
+/// 64-bit FNV-1a, stable across builds and platforms unlike the std hashers.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where
     P: AsRef<Path>,
@@ -47,11 +62,40 @@ where
     Ok(io::BufReader::new(file).lines())
 }
 
-/// Starts from index 0 of `subs`.
-fn parse_bezier_control_points(subs: &[&str]) -> Result<PlatformBezierControlPoint> {
+/// Field at `index` of a whitespace separated line.
+fn field<'a>(subs: &[&'a str], index: usize) -> Result<&'a str> {
+    subs.get(index).copied().ok_or_else(|| {
+        anyhow!(
+            "Expected at least {} fields, found {}",
+            index + 1,
+            subs.len()
+        )
+    })
+}
+
+fn parse_field<T>(subs: &[&str], index: usize) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = field(subs, index)?;
+    value
+        .parse()
+        .with_context(|| format!("Invalid value `{}` in field {}", value, index + 1))
+}
+
+fn parse_music_position(subs: &[&str], index: usize) -> Result<MusicPosition> {
+    Ok(MusicPosition::new(
+        parse_field(subs, index)?,
+        parse_field(subs, index + 1)?,
+    ))
+}
+
+/// Starts from index `index` of `subs`.
+fn parse_bezier_control_points(subs: &[&str], index: usize) -> Result<PlatformBezierControlPoint> {
     Ok(PlatformBezierControlPoint {
-        music_position: MusicPosition::new(subs[0].parse()?, subs[1].parse()?),
-        placement_offset: subs[2].parse()?,
+        music_position: parse_music_position(subs, index)?,
+        placement_offset: parse_field(subs, index + 2)?,
     })
 }
 
@@ -68,17 +112,17 @@ fn parse_is_left(val: &str) -> Result<bool> {
 /// Starts from index 1 of `subs`.
 fn parse_common_platform_parameters(subs: &[&str]) -> Result<CommonPlatformParameters> {
     Ok(CommonPlatformParameters {
-        start_music_position: MusicPosition::new(subs[1].parse()?, subs[2].parse()?),
-        end_music_position: MusicPosition::new(subs[3].parse()?, subs[4].parse()?),
-        start_placement_offset: subs[5].parse()?,
-        end_placement_offset: subs[6].parse()?,
-        start_width: subs[7].parse()?,
-        end_width: subs[8].parse()?,
+        start_music_position: parse_music_position(subs, 1)?,
+        end_music_position: parse_music_position(subs, 3)?,
+        start_placement_offset: parse_field(subs, 5)?,
+        end_placement_offset: parse_field(subs, 6)?,
+        start_width: parse_field(subs, 7)?,
+        end_width: parse_field(subs, 8)?,
     })
 }
 
 fn parse_platform(subs: &[&str]) -> Result<Platform> {
-    let platform_type = PlatformType::try_from(subs[0]).unwrap();
+    let platform_type = PlatformType::try_from(field(subs, 0)?)?;
     let platform;
 
     match platform_type {
@@ -98,12 +142,12 @@ fn parse_platform(subs: &[&str]) -> Result<Platform> {
             platform = Platform::DoubleSidedBezier(DoubleSidedBezierPlatform {
                 params: parse_common_platform_parameters(subs)?,
                 left_side_control_points: (
-                    parse_bezier_control_points(&subs[9..])?,
-                    parse_bezier_control_points(&subs[12..])?,
+                    parse_bezier_control_points(subs, 9)?,
+                    parse_bezier_control_points(subs, 12)?,
                 ),
                 right_side_control_points: (
-                    parse_bezier_control_points(&subs[15..])?,
-                    parse_bezier_control_points(&subs[18..])?,
+                    parse_bezier_control_points(subs, 15)?,
+                    parse_bezier_control_points(subs, 18)?,
                 ),
             })
         }
@@ -111,20 +155,20 @@ fn parse_platform(subs: &[&str]) -> Result<Platform> {
             platform = Platform::DoubleSidedParallelBezier(DoubleSidedParallelBezierPlatform {
                 params: parse_common_platform_parameters(subs)?,
                 control_points: (
-                    parse_bezier_control_points(&subs[9..])?,
-                    parse_bezier_control_points(&subs[12..])?,
+                    parse_bezier_control_points(subs, 9)?,
+                    parse_bezier_control_points(subs, 12)?,
                 ),
-                width: subs[15].parse()?,
+                width: parse_field(subs, 15)?,
             })
         }
         PlatformType::SingleSidedBezier => {
             platform = Platform::SingleSidedBezier(SingleSideBezierPlatform {
                 params: parse_common_platform_parameters(subs)?,
                 control_points: (
-                    parse_bezier_control_points(&subs[9..])?,
-                    parse_bezier_control_points(&subs[12..])?,
+                    parse_bezier_control_points(subs, 9)?,
+                    parse_bezier_control_points(subs, 12)?,
                 ),
-                is_left: parse_is_left(&subs[15])?,
+                is_left: parse_is_left(field(subs, 15)?)?,
             })
         }
     };
@@ -132,6 +176,56 @@ fn parse_platform(subs: &[&str]) -> Result<Platform> {
     Ok(platform)
 }
 
+/// Parses a line of data under `tag` into `chart_info`.
+fn parse_tag_line(chart_info: &mut ChartInfo, tag: &Tag, line: &str) -> Result<()> {
+    let subs = line.split_whitespace().collect::<Vec<_>>();
+    match tag {
+        Tag::StartingBpm => chart_info.starting_bpm = parse_field(&subs, 0)?,
+        Tag::StartingMeasure => {
+            chart_info.starting_measure = TimeSignature {
+                num_beats: parse_field(&subs, 0)?,
+                note_value: parse_field(&subs, 1)?,
+            }
+        }
+        Tag::BpmChanges => chart_info.bpm_changes.push(BpmChange {
+            music_position: parse_music_position(&subs, 0)?,
+            bpm: parse_field(&subs, 2)?,
+        }),
+        Tag::MeasureChanges => chart_info.measure_changes.push(MeasureChange {
+            music_position: parse_music_position(&subs, 0)?,
+            time_signature: TimeSignature {
+                num_beats: parse_field(&subs, 2)?,
+                note_value: parse_field(&subs, 3)?,
+            },
+        }),
+        Tag::PlayfieldChanges => chart_info
+            .playfield_speed_changes
+            .push(PlayfieldSpeedChange {
+                music_position: parse_music_position(&subs, 0)?,
+                duration: parse_field(&subs, 2)?,
+                mutiplier: parse_field(&subs, 3)?,
+            }),
+        Tag::Platforms => chart_info.platforms.push(parse_platform(&subs)?),
+        Tag::Notes => chart_info.notes.push(Note {
+            note_type: NoteInputType::try_from(field(&subs, 0)?)?,
+            music_position: parse_music_position(&subs, 1)?,
+            cell: parse_field(&subs, 3)?,
+            width: parse_field(&subs, 4)?,
+            // Optional end measure and offset for hold notes.
+            end_music_position: if subs.len() > 5 {
+                Some(parse_music_position(&subs, 5)?)
+            } else {
+                None
+            },
+        }),
+        Tag::MusicFilePath => chart_info.music_file_path = String::from(line),
+        Tag::MusicStartingOffset => chart_info.music_starting_offset = parse_field(&subs, 0)?,
+        Tag::Difficulty => chart_info.difficulty = String::from(line),
+        Tag::HitsoundPack => chart_info.hitsound_pack = String::from(line),
+    }
+    Ok(())
+}
+
 fn parse_chart_file_to_chart_info(file_path: &str) -> Result<ChartInfo> {
     let lines = read_lines(file_path)?;
 
@@ -148,68 +242,40 @@ fn parse_chart_file_to_chart_info(file_path: &str) -> Result<ChartInfo> {
         playfield_speed_changes: Vec::new(),
         music_file_path: String::new(),
         music_starting_offset: 0.0,
+        difficulty: String::new(),
+        hitsound_pack: String::new(),
+        chart_hash: fnv1a_hash(&std::fs::read(file_path)?),
     };
 
-    // XXX: Properly handle `unwrap`s and progate error.
-    let chart_info = lines
-        .flatten()
-        .fold((initial_chart_info, None::<Tag>), |acc, line| {
+    let (chart_info, _) = lines.enumerate().try_fold(
+        (initial_chart_info, None::<Tag>),
+        |(mut chart_info, current_tag), (line_index, line)| -> Result<_> {
+            let line = line?;
             let line = line.trim();
-
-            let mut chart_info = acc.0;
-            let mut current_tag = acc.1;
-
-            if !line.is_empty() && !line.starts_with(COMMENT_STR) {
-                if let Some(tag) = &current_tag {
-                    if let Ok(new_tag) = Tag::try_from(line) {
-                        current_tag = Some(new_tag);
-                    } else {
-                        let subs = line.split_whitespace().collect::<Vec<_>>();
-                        match tag {
-                            Tag::StartingBpm => chart_info.starting_bpm = subs[0].parse().unwrap(),
-                            Tag::StartingMeasure => {
-                                chart_info.starting_measure = TimeSignature {
-                                    num_beats: subs[0].parse().unwrap(),
-                                    note_value: subs[1].parse().unwrap(),
-                                }
-                            }
-                            Tag::Platforms => {
-                                chart_info.platforms.push(parse_platform(&subs).unwrap());
-                            }
-                            // Tag::Notes => chart_info.notes.push(Note {
-                            //     note_type: NoteType::try_from(subs[0]).unwrap(),
-                            //     music_position: MusicPosition::new(
-                            //         subs[1].parse().unwrap(),
-                            //         subs[2].parse().unwrap(),
-                            //     ),
-                            //     cell: subs[3].parse().unwrap(),
-                            //     width: subs[4].parse().unwrap(),
-                            // }),
-                            Tag::MusicFilePath => {
-                                chart_info.music_file_path = String::from(line.trim())
-                            }
-                            Tag::MusicStartingOffset => {
-                                chart_info.music_starting_offset = subs[0].parse().unwrap()
-                            }
-                            _ => {
-                                todo!()
-                            }
-                        }
-                    }
-                } else {
-                    current_tag = Some(Tag::try_from(line).unwrap());
-                }
+            if line.is_empty() || line.starts_with(COMMENT_STR) {
+                return Ok((chart_info, current_tag));
             }
 
-            (chart_info, current_tag)
-        })
-        .0;
+            let current_tag = match (Tag::try_from(line), current_tag) {
+                (Ok(new_tag), _) => new_tag,
+                (Err(_), Some(tag)) => {
+                    parse_tag_line(&mut chart_info, &tag, line)
+                        .with_context(|| format!("{}:{}", file_path, line_index + 1))?;
+                    tag
+                }
+                (Err(err), None) => {
+                    return Err(err.context(format!("{}:{}", file_path, line_index + 1)))
+                }
+            };
+            Ok((chart_info, Some(current_tag)))
+        },
+    )?;
 
     Ok(chart_info)
 }
 
-pub fn parse_chart_file(file_path: &str) -> Result<RuntimeChart> {
+pub fn parse_chart_file(file_path: &str, modifiers: &ChartModifiers) -> Result<RuntimeChart> {
     let chart_info = parse_chart_file_to_chart_info(file_path)?;
-    let chart = chart_info.create_runtime_chart()?;
+    let chart = chart_info.create_runtime_chart(modifiers)?;
     Ok(chart)
 }
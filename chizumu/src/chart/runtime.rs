@@ -3,11 +3,16 @@ use nalgebra::Vector2;
 
 use crate::chart::MusicPositionable;
 
-use super::{ChartInfo, MusicPosition, Platform};
+use super::{
+    modifiers::ChartModifiers, tempo::TempoMap, ChartInfo, MusicPosition, NoteInputType, Platform,
+    PlatformBezierControlPoint, NUM_CELLS,
+};
 
 use chizumu_rendering::{
-    game_components::{HitObject, PlatformObject, CURVE_SIDED_PLATFORM_BEZIER_SUBDIVISONS},
-    mesh::plane::Plane,
+    game_components::{
+        HitObject, HitObjectFade, PlatformObject, CURVE_SIDED_PLATFORM_BEZIER_SUBDIVISONS,
+    },
+    mesh::plane::{cubic_bezier_x_at_z, Plane},
     HIT_AREA_Z_START,
 };
 
@@ -27,35 +32,127 @@ impl RuntimePlatform {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct RuntimeNote {
+    pub note_type: NoteInputType,
     /// Offset in seconds from the start of the piece.
     pub offset: f32,
+    /// End offset in seconds for notes that need to be held.
+    pub end_offset: Option<f32>,
     pub cell: u32,
     pub width: u32,
 }
 
 impl RuntimeNote {
-    pub fn new(offset: f32, cell: u32, width: u32) -> Self {
+    pub fn new(
+        note_type: NoteInputType,
+        offset: f32,
+        end_offset: Option<f32>,
+        cell: u32,
+        width: u32,
+    ) -> Self {
         Self {
+            note_type,
             offset,
+            end_offset,
             cell,
             width,
         }
     }
+
+    pub fn is_hold(&self) -> bool {
+        self.end_offset.is_some()
+    }
+}
+
+#[derive(Clone)]
+struct RuntimePlayfieldSpeedChange {
+    /// Start and end in seconds from the start of the piece.
+    start: f32,
+    end: f32,
+    mutiplier: f32,
+}
+
+/// Maps song time to the distance covered by the runner, taking the chart's playfield speed changes into account.
+/// Every playfield object's position is derived from this so they stay in sync with the music.
+pub struct RunnerSpeedMap {
+    /// Distance covered by the runner per second without any speed changes.
+    base_speed: f32,
+    speed_changes: Vec<RuntimePlayfieldSpeedChange>,
+}
+
+impl RunnerSpeedMap {
+    /// Runner position at `secs` seconds from the start of the piece.
+    pub fn runner_position(&self, secs: f32) -> f32 {
+        self.speed_changes
+            .iter()
+            .filter(|change| change.start < secs)
+            .fold(self.base_speed * secs, |position, change| {
+                let duration = change.end.min(secs) - change.start;
+                position + duration * self.base_speed * (change.mutiplier - 1.0)
+            })
+    }
+}
+
+/// Horizontal range covered by a platform at a point in time.
+#[derive(Debug, Clone, Copy)]
+pub struct PlatformExtent {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl PlatformExtent {
+    pub fn contains(&self, x: f32) -> bool {
+        self.left <= x && x <= self.right
+    }
+
+    pub fn center(&self) -> f32 {
+        (self.left + self.right) / 2.0
+    }
 }
 
 /// Structure used by the main game logic during run time.
 pub struct RuntimeChart {
     notes: Vec<RuntimeNote>,
     platforms: Vec<RuntimePlatform>,
+    playfield_speed_changes: Vec<RuntimePlayfieldSpeedChange>,
+    modifiers: ChartModifiers,
 
+    pub tempo_map: TempoMap,
     pub chart_info: ChartInfo,
 }
 
 impl RuntimeChart {
-    pub fn create_hit_objects(&self) -> Vec<HitObject> {
-        let play_field_speed = 7.0; // z-axis movement per second.
-        let num_lanes = 10.0; // Number of individual lanes.
+    /// Notes sorted by offset.
+    pub fn notes(&self) -> &[RuntimeNote] {
+        &self.notes
+    }
+
+    /// Song time in seconds the last note or platform ends at.
+    pub fn end_offset(&self) -> f32 {
+        let notes_end = self
+            .notes
+            .iter()
+            .map(|note| note.end_offset.unwrap_or(note.offset));
+        let platforms_end = self.platforms.iter().map(|p| p.end_music_position);
+        notes_end.chain(platforms_end).fold(0.0, f32::max)
+    }
+
+    /// Modifiers the chart was built with.
+    pub fn modifiers(&self) -> &ChartModifiers {
+        &self.modifiers
+    }
+
+    /// `runner_speed` - distance covered by runner per second, scaled by the speed multiplier modifier.
+    pub fn create_runner_speed_map(&self, runner_speed: f32) -> RunnerSpeedMap {
+        RunnerSpeedMap {
+            base_speed: runner_speed * self.modifiers.speed_multiplier,
+            speed_changes: self.playfield_speed_changes.clone(),
+        }
+    }
+
+    pub fn create_hit_objects(&self, speed_map: &RunnerSpeedMap) -> Vec<HitObject> {
+        let num_lanes = NUM_CELLS as f32; // Number of individual lanes.
 
         let lane_scale = 1.0 / num_lanes; // Scale amount for one individual lane.
         let lane_left_edge_offset = -1.0; // X axis offset for leftmost lane.
@@ -68,22 +165,152 @@ impl RuntimeChart {
             .map(|note| HitObject {
                 x_scale: lane_scale * note.width as f32,
                 x_offset: lane_left_edge_offset + (note.cell as f32 * lane_width),
-                z_offset: (play_field_speed * note.offset) + HIT_AREA_Z_START,
+                z_offset: speed_map.runner_position(note.offset) + HIT_AREA_Z_START,
             })
             .collect::<Vec<_>>()
     }
 
-    /// `runner_speed` - distance covered by runner per second.
-    pub fn create_platform_objects(&self, runner_speed: f32) -> Vec<PlatformObject> {
+    pub fn hit_object_fade(&self) -> HitObjectFade {
+        HitObjectFade {
+            hidden_distance: self.modifiers.hidden_distance,
+            sudden_distance: self.modifiers.sudden_distance,
+        }
+    }
+
+    /// Extents of the platforms placed at `secs` seconds, empty where there is no platform.
+    /// Edges are evaluated the same way the platform meshes are built, so extents match what is drawn.
+    pub fn platform_extents(&self, speed_map: &RunnerSpeedMap, secs: f32) -> Vec<PlatformExtent> {
+        self.platforms
+            .iter()
+            .filter(|p| p.start_music_position <= secs && secs <= p.end_music_position)
+            .map(|p| self.platform_extent(p, speed_map, secs))
+            .collect()
+    }
+
+    fn platform_extent(
+        &self,
+        p: &RuntimePlatform,
+        speed_map: &RunnerSpeedMap,
+        secs: f32,
+    ) -> PlatformExtent {
+        // Z axis positions are relative to the start of the platform, as for the meshes.
+        let start_runner_position = speed_map.runner_position(p.start_music_position);
+        let z_length = speed_map.runner_position(p.end_music_position) - start_runner_position;
+        let z = speed_map.runner_position(secs) - start_runner_position;
+
+        let control_point_2d = |control_point: &PlatformBezierControlPoint| {
+            Vector2::new(
+                control_point.placement_offset,
+                speed_map.runner_position(
+                    self.tempo_map
+                        .music_position_to_seconds(&control_point.music_position),
+                ) - start_runner_position,
+            )
+        };
+        let curved_x = |start: f32,
+                        end: f32,
+                        control_points: &(
+            PlatformBezierControlPoint,
+            PlatformBezierControlPoint,
+        )| {
+            cubic_bezier_x_at_z(
+                Vector2::new(start, 0.0),
+                Vector2::new(end, z_length),
+                (
+                    control_point_2d(&control_points.0),
+                    control_point_2d(&control_points.1),
+                ),
+                z,
+            )
+        };
+        let straight_x = |start: f32, end: f32| {
+            if z_length > 0.0 {
+                start + (end - start) * (z / z_length)
+            } else {
+                start
+            }
+        };
+
+        let (left, right) = match &p.platform {
+            Platform::DynamicQuad(platform) => {
+                let params = &platform.params;
+                (
+                    straight_x(params.start_placement_offset, params.end_placement_offset),
+                    straight_x(
+                        params.start_placement_offset + params.start_width,
+                        params.end_placement_offset + params.end_width,
+                    ),
+                )
+            }
+            Platform::DoubleSidedBezier(platform) => {
+                let params = &platform.params;
+                (
+                    curved_x(
+                        params.start_placement_offset,
+                        params.end_placement_offset,
+                        &platform.left_side_control_points,
+                    ),
+                    curved_x(
+                        params.start_placement_offset + params.start_width,
+                        params.end_placement_offset + params.end_width,
+                        &platform.right_side_control_points,
+                    ),
+                )
+            }
+            Platform::DoubleSidedParallelBezier(platform) => {
+                let params = &platform.params;
+                let left = curved_x(
+                    params.start_placement_offset,
+                    params.end_placement_offset,
+                    &platform.control_points,
+                );
+                (left, left + platform.width)
+            }
+            Platform::SingleSidedBezier(platform) => {
+                let params = &platform.params;
+                let (start, end) = (params.start_placement_offset, params.end_placement_offset);
+                let (start_right, end_right) = (
+                    params.start_placement_offset + params.start_width,
+                    params.end_placement_offset + params.end_width,
+                );
+                if platform.is_left {
+                    (
+                        curved_x(start, end, &platform.control_points),
+                        straight_x(start_right, end_right),
+                    )
+                } else {
+                    (
+                        straight_x(start, end),
+                        curved_x(start_right, end_right, &platform.control_points),
+                    )
+                }
+            }
+        };
+
+        PlatformExtent {
+            left: left.min(right),
+            right: left.max(right),
+        }
+    }
+
+    pub fn create_platform_objects(&self, speed_map: &RunnerSpeedMap) -> Vec<PlatformObject> {
         self.platforms
             .iter()
             .map(|p| {
-                let start_runner_position = p.start_music_position * runner_speed;
-                let end_runner_position = p.end_music_position * runner_speed;
+                let start_runner_position = speed_map.runner_position(p.start_music_position);
+                let end_runner_position = speed_map.runner_position(p.end_music_position);
                 let z_length = end_runner_position - start_runner_position;
                 let z_offset = HIT_AREA_Z_START;
                 let bezier_subdivisions = CURVE_SIDED_PLATFORM_BEZIER_SUBDIVISONS as _;
 
+                // Z axis position of a bezier control point relative to the start of the platform.
+                let control_point_z = |music_position: &MusicPosition| {
+                    speed_map
+                        .runner_position(self.tempo_map.music_position_to_seconds(music_position))
+                        - start_runner_position
+                        + z_offset
+                };
+
                 let plane_mesh = match &p.platform {
                     Platform::DynamicQuad(platform) => {
                         let params = &platform.params;
@@ -103,19 +330,10 @@ impl RuntimeChart {
                     Platform::DoubleSidedBezier(platform) => {
                         let params = &platform.params;
                         // XXX TODO: Make utility function for bezier 2d coord conversion these.
-                        // XXX TODO: Make utiity function for music positition seconds to runner position.
                         let left_side_control_points = &platform.left_side_control_points;
                         let left_side_control_points_z = (
-                            self.chart_info.music_position_to_seconds(
-                                &platform.left_side_control_points.0.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
-                            self.chart_info.music_position_to_seconds(
-                                &platform.left_side_control_points.1.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
+                            control_point_z(&platform.left_side_control_points.0.music_position),
+                            control_point_z(&platform.left_side_control_points.1.music_position),
                         );
                         let left_side_control_points_2d = (
                             Vector2::new(
@@ -130,16 +348,8 @@ impl RuntimeChart {
 
                         let right_side_control_points = &platform.right_side_control_points;
                         let right_side_control_points_z = (
-                            self.chart_info.music_position_to_seconds(
-                                &platform.right_side_control_points.0.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
-                            self.chart_info.music_position_to_seconds(
-                                &platform.right_side_control_points.1.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
+                            control_point_z(&platform.right_side_control_points.0.music_position),
+                            control_point_z(&platform.right_side_control_points.1.music_position),
                         );
                         let right_side_control_points_2d = (
                             Vector2::new(
@@ -173,16 +383,8 @@ impl RuntimeChart {
 
                         let control_points = &platform.control_points;
                         let control_points_z = (
-                            self.chart_info.music_position_to_seconds(
-                                &platform.control_points.0.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
-                            self.chart_info.music_position_to_seconds(
-                                &platform.control_points.1.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
+                            control_point_z(&platform.control_points.0.music_position),
+                            control_point_z(&platform.control_points.1.music_position),
                         );
                         let control_points_2d = (
                             Vector2::new(control_points.0.placement_offset, control_points_z.0),
@@ -202,16 +404,8 @@ impl RuntimeChart {
 
                         let control_points = &platform.control_points;
                         let control_points_z = (
-                            self.chart_info.music_position_to_seconds(
-                                &platform.control_points.0.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
-                            self.chart_info.music_position_to_seconds(
-                                &platform.control_points.1.music_position,
-                            ) * runner_speed
-                                - start_runner_position
-                                + z_offset,
+                            control_point_z(&platform.control_points.0.music_position),
+                            control_point_z(&platform.control_points.1.music_position),
                         );
                         let control_points_2d = (
                             Vector2::new(control_points.0.placement_offset, control_points_z.0),
@@ -269,40 +463,68 @@ impl RuntimeChart {
 }
 
 impl ChartInfo {
-    fn music_position_to_seconds(&self, music_position: &MusicPosition) -> f32 {
-        let seconds_per_minute = 60.0;
-        let time_per_measure = seconds_per_minute
-            / (self.starting_bpm as f32 / self.starting_measure.num_beats as f32);
-        self.music_starting_offset
-            + ((music_position.measure as f32 * time_per_measure)
-                + (time_per_measure * music_position.offset))
+    fn create_tempo_map(&self) -> TempoMap {
+        TempoMap::new(
+            self.starting_bpm,
+            &self.starting_measure,
+            &self.bpm_changes,
+            &self.measure_changes,
+            self.music_starting_offset,
+        )
     }
 
-    pub fn create_runtime_chart(self) -> Result<RuntimeChart> {
+    pub fn create_runtime_chart(self, modifiers: &ChartModifiers) -> Result<RuntimeChart> {
         log::debug!("{:#?}", self);
 
+        let tempo_map = self.create_tempo_map();
+
         let platforms = self
             .platforms
             .iter()
             .map(|p| RuntimePlatform {
-                platform: p.clone(),
-                start_music_position: self.music_position_to_seconds(&p.start_music_position()),
-                end_music_position: self.music_position_to_seconds(&p.end_music_position()),
+                platform: modifiers.apply_to_platform(p),
+                start_music_position: tempo_map
+                    .music_position_to_seconds(&p.start_music_position()),
+                end_music_position: tempo_map.music_position_to_seconds(&p.end_music_position()),
             })
             .collect::<Vec<_>>();
 
+        let playfield_speed_changes = self
+            .playfield_speed_changes
+            .iter()
+            .map(|change| {
+                let start = tempo_map.music_position_to_seconds(&change.music_position);
+                RuntimePlayfieldSpeedChange {
+                    start,
+                    end: start + change.duration,
+                    mutiplier: change.mutiplier,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut chart_notes = self.notes.clone();
+        modifiers.apply_to_notes(&mut chart_notes);
+
         let mut notes = Vec::new();
-        for note in &self.notes {
+        for note in &chart_notes {
             notes.push(RuntimeNote::new(
-                self.music_position_to_seconds(&note.music_position),
+                note.note_type,
+                tempo_map.music_position_to_seconds(&note.music_position),
+                note.end_music_position
+                    .as_ref()
+                    .map(|p| tempo_map.music_position_to_seconds(p)),
                 note.cell,
                 note.width,
             ))
         }
+        notes.sort_by(|a, b| a.offset.total_cmp(&b.offset));
 
         let chart = RuntimeChart {
             notes,
             platforms,
+            playfield_speed_changes,
+            modifiers: *modifiers,
+            tempo_map,
             chart_info: self,
         };
         Ok(chart)
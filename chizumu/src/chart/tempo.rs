@@ -0,0 +1,247 @@
+/*!
+ * Tempo mapping between music positions and song time.
+ */
+
+use super::{BpmChange, MeasureChange, MusicPosition, TimeSignature};
+
+/// A span of the chart with a constant bpm and time signature.
+#[derive(Debug, Clone)]
+struct TempoSegment {
+    /// Start of the segment in global measures, i.e. measure + offset within the measure.
+    start_position: f32,
+    /// Start of the segment in seconds from the start of the music.
+    start_seconds: f32,
+    bpm: u32,
+    time_signature: TimeSignature,
+}
+
+impl TempoSegment {
+    fn seconds_per_measure(&self) -> f32 {
+        let seconds_per_minute = 60.0;
+        seconds_per_minute / (self.bpm as f32 / self.time_signature.num_beats as f32)
+    }
+
+    fn position_to_seconds(&self, position: f32) -> f32 {
+        self.start_seconds + (position - self.start_position) * self.seconds_per_measure()
+    }
+
+    fn seconds_to_position(&self, seconds: f32) -> f32 {
+        self.start_position + (seconds - self.start_seconds) / self.seconds_per_measure()
+    }
+}
+
+/// Timing event crossed while the music is playing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TempoEvent {
+    /// Start of a measure. Always followed by the `Beat` event of the first beat of the measure.
+    Measure {
+        measure: u32,
+        time: f32,
+    },
+    /// `beat` is the beat index within the measure, starting from 0.
+    Beat {
+        measure: u32,
+        beat: u32,
+        time: f32,
+    },
+    BpmChange {
+        bpm: u32,
+        time: f32,
+    },
+    TimeSignatureChange {
+        num_beats: u32,
+        note_value: u32,
+        time: f32,
+    },
+}
+
+impl TempoEvent {
+    /// Song time in seconds at which the event takes place.
+    pub fn time(&self) -> f32 {
+        match self {
+            Self::Measure { time, .. } => *time,
+            Self::Beat { time, .. } => *time,
+            Self::BpmChange { time, .. } => *time,
+            Self::TimeSignatureChange { time, .. } => *time,
+        }
+    }
+}
+
+/// Piecewise mapping of the chart's bpm and time signature changes.
+/// Time signature changes are expected to take place at the start of a measure, beats are always counted from the
+/// start of the current measure.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    /// Sorted by start position, the first segment always starts at measure 0.
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    pub(super) fn new(
+        starting_bpm: u32,
+        starting_measure: &TimeSignature,
+        bpm_changes: &[BpmChange],
+        measure_changes: &[MeasureChange],
+        music_starting_offset: f32,
+    ) -> Self {
+        let mut changes = bpm_changes
+            .iter()
+            .map(|c| (position_of(&c.music_position), Some(c.bpm), None))
+            .chain(measure_changes.iter().map(|c| {
+                (
+                    position_of(&c.music_position),
+                    None,
+                    Some(c.time_signature.clone()),
+                )
+            }))
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut segments = vec![TempoSegment {
+            start_position: 0.0,
+            start_seconds: music_starting_offset,
+            bpm: starting_bpm,
+            time_signature: starting_measure.clone(),
+        }];
+
+        for (position, bpm, time_signature) in changes {
+            // Changes at the same position are merged into a single segment.
+            if segments.last().unwrap().start_position == position {
+                let merged = segments.pop().unwrap();
+                segments.push(TempoSegment {
+                    bpm: bpm.unwrap_or(merged.bpm),
+                    time_signature: time_signature.unwrap_or(merged.time_signature),
+                    ..merged
+                });
+                continue;
+            }
+
+            let last = segments.last().unwrap();
+            let segment = TempoSegment {
+                start_position: position,
+                start_seconds: last.position_to_seconds(position),
+                bpm: bpm.unwrap_or(last.bpm),
+                time_signature: time_signature.unwrap_or_else(|| last.time_signature.clone()),
+            };
+            segments.push(segment);
+        }
+
+        Self { segments }
+    }
+
+    fn segment_index_at_position(&self, position: f32) -> usize {
+        self.segments
+            .partition_point(|s| s.start_position <= position)
+            .max(1)
+            - 1
+    }
+
+    fn segment_index_at_seconds(&self, seconds: f32) -> usize {
+        self.segments
+            .partition_point(|s| s.start_seconds <= seconds)
+            .max(1)
+            - 1
+    }
+
+    /// `position` is given in global measures, i.e. measure + offset within the measure.
+    pub fn position_to_seconds(&self, position: f32) -> f32 {
+        self.segments[self.segment_index_at_position(position)].position_to_seconds(position)
+    }
+
+    /// Returns the position in global measures. Negative before the first measure.
+    pub fn seconds_to_position(&self, seconds: f32) -> f32 {
+        self.segments[self.segment_index_at_seconds(seconds)].seconds_to_position(seconds)
+    }
+
+    pub(super) fn music_position_to_seconds(&self, music_position: &MusicPosition) -> f32 {
+        self.position_to_seconds(position_of(music_position))
+    }
+
+    /// Appends all events that take place after `start_seconds` up to and including `end_seconds`, in order.
+    /// Nothing is emitted before the first measure.
+    pub fn events_between(
+        &self,
+        start_seconds: f32,
+        end_seconds: f32,
+        events: &mut Vec<TempoEvent>,
+    ) {
+        if end_seconds <= start_seconds {
+            return;
+        }
+
+        let start_position = self.seconds_to_position(start_seconds);
+        let end_position = self.seconds_to_position(end_seconds);
+        if end_position < 0.0 {
+            return;
+        }
+
+        let first_segment = self.segment_index_at_position(start_position.max(0.0));
+        let last_segment = self.segment_index_at_position(end_position);
+
+        for (index, segment) in self
+            .segments
+            .iter()
+            .enumerate()
+            .take(last_segment + 1)
+            .skip(first_segment)
+        {
+            let segment_end_position = self
+                .segments
+                .get(index + 1)
+                .map_or(f32::MAX, |s| s.start_position);
+            let in_range = |position: f32| {
+                position > start_position
+                    && position <= end_position
+                    && position >= segment.start_position
+                    && position < segment_end_position
+                    && position >= 0.0
+            };
+
+            if index > 0 && in_range(segment.start_position) {
+                let previous = &self.segments[index - 1];
+                if previous.bpm != segment.bpm {
+                    events.push(TempoEvent::BpmChange {
+                        bpm: segment.bpm,
+                        time: segment.start_seconds,
+                    });
+                }
+                if previous.time_signature.num_beats != segment.time_signature.num_beats
+                    || previous.time_signature.note_value != segment.time_signature.note_value
+                {
+                    events.push(TempoEvent::TimeSignatureChange {
+                        num_beats: segment.time_signature.num_beats,
+                        note_value: segment.time_signature.note_value,
+                        time: segment.start_seconds,
+                    });
+                }
+            }
+
+            let num_beats = segment.time_signature.num_beats.max(1);
+            let first_measure = start_position.max(segment.start_position).max(0.0).floor() as u32;
+            let last_measure = end_position.min(segment_end_position).floor() as u32;
+
+            for measure in first_measure..=last_measure {
+                for beat in 0..num_beats {
+                    let position = measure as f32 + beat as f32 / num_beats as f32;
+                    if !in_range(position) {
+                        continue;
+                    }
+
+                    let time = segment.position_to_seconds(position);
+                    if beat == 0 {
+                        events.push(TempoEvent::Measure { measure, time });
+                    }
+                    events.push(TempoEvent::Beat {
+                        measure,
+                        beat,
+                        time,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn position_of(music_position: &MusicPosition) -> f32 {
+    music_position.measure as f32 + music_position.offset
+}
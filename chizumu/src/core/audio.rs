@@ -1,63 +1,198 @@
-use anyhow::Result;
+/*!
+ * The audio service shared across the game. Music, hitsounds and UI sounds play on mixer sub-tracks of their own,
+ * each with its own volume. `AudioSystem` is a cheap handle, clones share the same audio manager so any thread can
+ * play sounds without owning a manager.
+ */
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Result};
 use kira::{
     manager::{backend::cpal::CpalBackend, AudioManager, AudioManagerSettings},
-    sound::static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings},
+    sound::{
+        static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings},
+        PlaybackState,
+    },
+    track::{TrackBuilder, TrackHandle},
     tween::Tween,
 };
+use parking_lot::Mutex;
 
-pub(crate) struct AudioSystem {
+use chizumu::game::clock::MusicClock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum AudioTrack {
+    Music,
+    Hitsounds,
+    Ui,
+}
+
+impl AudioTrack {
+    const ALL: [AudioTrack; 3] = [AudioTrack::Music, AudioTrack::Hitsounds, AudioTrack::Ui];
+}
+
+struct AudioState {
     audio_manager: AudioManager,
+    /// Mixer tracks are removed when their handle is dropped, they are kept for as long as the manager.
+    tracks: HashMap<AudioTrack, TrackHandle>,
 
-    sound_data_effects: Vec<StaticSoundData>,
+    /// Keyed by sound effect id, each routed to the track it was loaded for.
+    sound_data_effects: HashMap<usize, StaticSoundData>,
+    next_sound_effect_id: usize,
     sound_data_music: Vec<StaticSoundData>,
 }
 
+impl AudioState {
+    fn track(&self, track: AudioTrack) -> &TrackHandle {
+        // Every track is added when the audio system is created.
+        &self.tracks[&track]
+    }
+}
+
+impl Drop for AudioState {
+    fn drop(&mut self) {
+        self.audio_manager
+            .pause(Tween {
+                ..Default::default()
+            })
+            .unwrap();
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AudioSystem {
+    state: Arc<Mutex<AudioState>>,
+}
+
 impl AudioSystem {
     pub(crate) fn new() -> Result<Self> {
-        let audio_manager = AudioManager::<CpalBackend>::new(AudioManagerSettings::default())?;
+        let mut audio_manager = AudioManager::<CpalBackend>::new(AudioManagerSettings::default())?;
 
-        let mut sound_data_effects = Vec::new();
-        sound_data_effects.push(StaticSoundData::from_file(
-            "assets/sound_effects/Arcaea/arc.wav",
-            StaticSoundSettings::new().volume(0.3),
-        )?);
+        let mut tracks = HashMap::new();
+        for track in AudioTrack::ALL {
+            tracks.insert(track, audio_manager.add_sub_track(TrackBuilder::new())?);
+        }
 
         Ok(Self {
-            audio_manager,
-            sound_data_effects,
-            sound_data_music: Vec::new(),
+            state: Arc::new(Mutex::new(AudioState {
+                audio_manager,
+                tracks,
+                sound_data_effects: HashMap::new(),
+                next_sound_effect_id: 0,
+                sound_data_music: Vec::new(),
+            })),
         })
     }
 
-    pub(crate) fn play_sound_effect(&mut self, sound_effect_index: usize) -> Result<()> {
-        self.audio_manager
-            .play(self.sound_data_effects[sound_effect_index].clone())?;
+    /// `volume` - amplitude of the sample relative to its track.
+    /// Returns id of the loaded sound effect, ids are never reused.
+    pub(crate) fn load_sound_effect(
+        &self,
+        file_path: &str,
+        track: AudioTrack,
+        volume: f64,
+    ) -> Result<usize> {
+        let mut state = self.state.lock();
+        let data = StaticSoundData::from_file(
+            file_path,
+            StaticSoundSettings::new()
+                .volume(volume)
+                .output_destination(state.track(track)),
+        )?;
+        let sound_effect_id = state.next_sound_effect_id;
+        state.next_sound_effect_id += 1;
+        state.sound_data_effects.insert(sound_effect_id, data);
+        Ok(sound_effect_id)
+    }
+
+    /// Frees the sound effect, sounds of it that are playing keep playing.
+    pub(crate) fn unload_sound_effect(&self, sound_effect_id: usize) {
+        self.state
+            .lock()
+            .sound_data_effects
+            .remove(&sound_effect_id);
+    }
+
+    /// Plays on the track the sound effect was loaded for.
+    pub(crate) fn play_sound_effect(&self, sound_effect_id: usize) -> Result<()> {
+        let mut state = self.state.lock();
+        let data = state
+            .sound_data_effects
+            .get(&sound_effect_id)
+            .ok_or_else(|| anyhow!("No sound effect with id {}", sound_effect_id))?
+            .clone();
+        state.audio_manager.play(data)?;
+        Ok(())
+    }
+
+    /// `volume` - amplitude of everything played on the track, 1.0 leaves samples as they are.
+    pub(crate) fn set_track_volume(&self, track: AudioTrack, volume: f64) -> Result<()> {
+        self.state
+            .lock()
+            .track(track)
+            .set_volume(volume, Tween::default())?;
         Ok(())
     }
 
     /// Returns index to loaded music
-    pub(crate) fn load_music_data(&mut self, music_file_path: &str) -> Result<usize> {
-        let data =
-            StaticSoundData::from_file(music_file_path, StaticSoundSettings::new().volume(0.1))?;
-        self.sound_data_music.push(data);
-        Ok(self.sound_data_music.len() - 1)
+    pub(crate) fn load_music_data(&self, music_file_path: &str) -> Result<usize> {
+        let mut state = self.state.lock();
+        let data = StaticSoundData::from_file(
+            music_file_path,
+            StaticSoundSettings::new()
+                .volume(0.1)
+                .output_destination(state.track(AudioTrack::Music)),
+        )?;
+        state.sound_data_music.push(data);
+        Ok(state.sound_data_music.len() - 1)
     }
 
-    pub fn play_music(&mut self, music_index: usize) -> Result<StaticSoundHandle> {
-        let sound_handle = self
-            .audio_manager
-            .play(self.sound_data_music[music_index].clone())?;
+    /// Frees all loaded music, invalidates every music index.
+    pub(crate) fn unload_music_data(&self) {
+        self.state.lock().sound_data_music.clear();
+    }
+
+    pub fn play_music(&self, music_index: usize) -> Result<StaticSoundHandle> {
+        let mut state = self.state.lock();
+        let data = state.sound_data_music[music_index].clone();
+        let sound_handle = state.audio_manager.play(data)?;
 
         Ok(sound_handle)
     }
 }
 
-impl Drop for AudioSystem {
-    fn drop(&mut self) {
-        self.audio_manager
-            .pause(Tween {
-                ..Default::default()
-            })
-            .unwrap();
+/// Clock backed by the playing music's sound handle.
+pub(crate) struct AudioClock {
+    sound_handle: StaticSoundHandle,
+}
+
+impl AudioClock {
+    pub(crate) fn new(sound_handle: StaticSoundHandle) -> Self {
+        Self { sound_handle }
+    }
+}
+
+impl MusicClock for AudioClock {
+    fn position(&self) -> f32 {
+        self.sound_handle.position() as f32
+    }
+
+    fn is_finished(&self) -> bool {
+        self.sound_handle.state() == PlaybackState::Stopped
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        self.sound_handle.pause(Tween::default())?;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.sound_handle.resume(Tween::default())?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.sound_handle.stop(Tween::default())?;
+        Ok(())
     }
 }
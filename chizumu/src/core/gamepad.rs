@@ -0,0 +1,169 @@
+/*!
+ * Gamepad and arcade controller input, buttons are bound to rhythm control inputs through per-device profiles and
+ * a stick steers the runner.
+ */
+
+use std::{
+    collections::HashMap,
+    time::{Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use gilrs::{Axis, Button, EventType, Gamepad, GamepadId, Gilrs};
+
+use super::{
+    input::RhythmControlInput,
+    keybindings::{GamepadAxisCode, GamepadButtonCode, Keybindings},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum GamepadEvent {
+    Button {
+        gamepad_id: usize,
+        button: GamepadButtonCode,
+        /// Input the button is bound to in the gamepad's profile.
+        input: Option<RhythmControlInput>,
+        pressed: bool,
+        /// When the OS received the event.
+        os_event: Instant,
+    },
+    /// Tilt of the steering axis of the gamepad's profile, in [-1, 1].
+    Steering(f32),
+    Disconnected {
+        gamepad_id: usize,
+        os_event: Instant,
+    },
+}
+
+pub(crate) struct GamepadInput {
+    gilrs: Gilrs,
+    /// Profile key of each connected gamepad, gamepads may be connected and disconnected at any time.
+    connected_gamepads: HashMap<GamepadId, String>,
+}
+
+impl GamepadInput {
+    pub(crate) fn new() -> Result<Self> {
+        let gilrs =
+            Gilrs::new().map_err(|err| anyhow!("Failed to initialize gamepad support: {}", err))?;
+
+        let connected_gamepads = gilrs
+            .gamepads()
+            .map(|(id, gamepad)| {
+                log::info!("Gamepad {} connected: {}", id, gamepad.name());
+                (id, profile_key(&gamepad))
+            })
+            .collect();
+
+        Ok(Self {
+            gilrs,
+            connected_gamepads,
+        })
+    }
+
+    /// Takes the events received since the last call, mapped through the profile of each gamepad.
+    pub(crate) fn poll(&mut self, keybindings: &Keybindings) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        while let Some(gilrs::Event { id, event, time }) = self.gilrs.next_event() {
+            let os_event = instant_from_system_time(time);
+            match event {
+                EventType::Connected => {
+                    let gamepad = self.gilrs.gamepad(id);
+                    log::info!("Gamepad {} connected: {}", id, gamepad.name());
+                    self.connected_gamepads.insert(id, profile_key(&gamepad));
+                }
+                EventType::Disconnected => {
+                    log::info!("Gamepad {} disconnected", id);
+                    self.connected_gamepads.remove(&id);
+                    events.push(GamepadEvent::Disconnected {
+                        gamepad_id: id.into(),
+                        os_event,
+                    });
+                }
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                    let (Some(button), Some(key)) =
+                        (button_code(button), self.connected_gamepads.get(&id))
+                    else {
+                        continue;
+                    };
+                    let input = keybindings
+                        .gamepad_profile(key)
+                        .input_map()
+                        .get(&button)
+                        .copied();
+                    events.push(GamepadEvent::Button {
+                        gamepad_id: id.into(),
+                        button,
+                        input,
+                        pressed: matches!(event, EventType::ButtonPressed(..)),
+                        os_event,
+                    });
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let Some(key) = self.connected_gamepads.get(&id) else {
+                        continue;
+                    };
+                    let profile = keybindings.gamepad_profile(key);
+                    let steering_axis = match profile.steering_axis {
+                        GamepadAxisCode::LeftStickX => Axis::LeftStickX,
+                        GamepadAxisCode::RightStickX => Axis::RightStickX,
+                    };
+                    if axis == steering_axis {
+                        let value = if profile.invert_steering {
+                            -value
+                        } else {
+                            value
+                        };
+                        events.push(GamepadEvent::Steering(value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+/// Gamepad events are stamped by the system clock when the OS received them, the instant is found by how long ago
+/// that was.
+fn instant_from_system_time(time: SystemTime) -> Instant {
+    let now = Instant::now();
+    SystemTime::now()
+        .duration_since(time)
+        .ok()
+        .and_then(|elapsed| now.checked_sub(elapsed))
+        .unwrap_or(now)
+}
+
+/// Profiles are keyed by UUID so the same model gets the same profile on every port.
+fn profile_key(gamepad: &Gamepad) -> String {
+    gamepad
+        .uuid()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn button_code(button: Button) -> Option<GamepadButtonCode> {
+    let button = match button {
+        Button::South => GamepadButtonCode::South,
+        Button::East => GamepadButtonCode::East,
+        Button::North => GamepadButtonCode::North,
+        Button::West => GamepadButtonCode::West,
+        Button::C => GamepadButtonCode::C,
+        Button::Z => GamepadButtonCode::Z,
+        Button::LeftTrigger => GamepadButtonCode::LeftBumper,
+        Button::RightTrigger => GamepadButtonCode::RightBumper,
+        Button::LeftTrigger2 => GamepadButtonCode::LeftTrigger,
+        Button::RightTrigger2 => GamepadButtonCode::RightTrigger,
+        Button::Select => GamepadButtonCode::Select,
+        Button::Start => GamepadButtonCode::Start,
+        Button::LeftThumb => GamepadButtonCode::LeftThumb,
+        Button::RightThumb => GamepadButtonCode::RightThumb,
+        Button::DPadUp => GamepadButtonCode::DPadUp,
+        Button::DPadDown => GamepadButtonCode::DPadDown,
+        Button::DPadLeft => GamepadButtonCode::DPadLeft,
+        Button::DPadRight => GamepadButtonCode::DPadRight,
+        Button::Mode | Button::Unknown => return None,
+    };
+    Some(button)
+}
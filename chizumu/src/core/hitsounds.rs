@@ -0,0 +1,206 @@
+/*!
+ * Hitsound packs, directories of samples with a manifest assigning samples to each kind of hit and judgement.
+ *
+ * `manifest.json` of a pack:
+ * {
+ *     "name": "Arcaea",
+ *     "samples": {
+ *         "tap": { "default": { "file": "arc.wav", "volume": 0.3 }, "good": { "file": "good.wav" } },
+ *         "miss": { "default": { "file": "miss.wav", "volume": 0.5 } }
+ *     }
+ * }
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use chizumu::{
+    chart::{runtime::RuntimeNote, NoteInputType},
+    game::judgement::Judgement,
+};
+
+use super::audio::{AudioSystem, AudioTrack};
+
+/// Every subdirectory with a manifest is a pack, named by its directory.
+pub(crate) const HITSOUND_PACKS_DIRECTORY: &str = "assets/sound_effects";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HitsoundKind {
+    Tap,
+    Wide,
+    Move,
+    HoldStart,
+    HoldEnd,
+    Miss,
+}
+
+impl HitsoundKind {
+    /// Kind of the hit of a note's head.
+    pub(crate) fn of_note(note: &RuntimeNote) -> Self {
+        if note.is_hold() {
+            return HitsoundKind::HoldStart;
+        }
+        match note.note_type {
+            NoteInputType::TapWidth => HitsoundKind::Wide,
+            NoteInputType::TapMove1 | NoteInputType::TapMove2 => HitsoundKind::Move,
+            _ => HitsoundKind::Tap,
+        }
+    }
+}
+
+/// Judgement a sample is played for, `Default` for judgements without a sample of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SampleJudgement {
+    Default,
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl From<Judgement> for SampleJudgement {
+    fn from(judgement: Judgement) -> Self {
+        match judgement {
+            Judgement::Perfect => SampleJudgement::Perfect,
+            Judgement::Great => SampleJudgement::Great,
+            Judgement::Good => SampleJudgement::Good,
+            Judgement::Miss => SampleJudgement::Miss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SampleManifest {
+    /// Relative to the pack's directory.
+    file: String,
+    #[serde(default = "default_sample_volume")]
+    volume: f64,
+}
+
+fn default_sample_volume() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HitsoundManifest {
+    name: String,
+    samples: BTreeMap<HitsoundKind, BTreeMap<SampleJudgement, SampleManifest>>,
+}
+
+/// A pack with its samples loaded as sound effects on the hitsound track.
+pub(crate) struct HitsoundPack {
+    /// Directory name the pack was loaded from.
+    pack_name: String,
+    sound_effects: HashMap<(HitsoundKind, SampleJudgement), usize>,
+    audio_system: AudioSystem,
+}
+
+impl HitsoundPack {
+    pub(crate) fn load(audio_system: &AudioSystem, pack_name: &str) -> Result<Self> {
+        // Names come from charts too, they must not lead out of the packs directory.
+        if pack_name.is_empty() || pack_name.contains(['/', '\\']) || pack_name.contains("..") {
+            return Err(anyhow!("Invalid hitsound pack name {:?}", pack_name));
+        }
+
+        let pack_directory = Path::new(HITSOUND_PACKS_DIRECTORY).join(pack_name);
+        let manifest: HitsoundManifest = serde_json::from_str(&std::fs::read_to_string(
+            pack_directory.join(MANIFEST_FILE_NAME),
+        )?)?;
+
+        let mut pack = Self {
+            pack_name: pack_name.to_string(),
+            sound_effects: HashMap::new(),
+            audio_system: audio_system.clone(),
+        };
+
+        // Samples shared by several kinds are loaded once.
+        let mut loaded_samples = HashMap::<(String, u64), usize>::new();
+        for (kind, samples) in &manifest.samples {
+            for (judgement, sample) in samples {
+                let sample_key = (sample.file.clone(), sample.volume.to_bits());
+                let sound_effect_id = match loaded_samples.get(&sample_key) {
+                    Some(sound_effect_id) => *sound_effect_id,
+                    None => {
+                        let file_path = pack_directory.join(&sample.file);
+                        let file_path = file_path
+                            .to_str()
+                            .ok_or_else(|| anyhow!("Invalid sample path {:?}", file_path))?;
+                        // Samples loaded so far are freed by the pack's drop if this fails.
+                        let sound_effect_id = audio_system.load_sound_effect(
+                            file_path,
+                            AudioTrack::Hitsounds,
+                            sample.volume,
+                        )?;
+                        loaded_samples.insert(sample_key, sound_effect_id);
+                        sound_effect_id
+                    }
+                };
+                pack.sound_effects
+                    .insert((*kind, *judgement), sound_effect_id);
+            }
+        }
+
+        log::info!("Loaded hitsound pack {} from {}", manifest.name, pack_name);
+        Ok(pack)
+    }
+
+    pub(crate) fn pack_name(&self) -> &str {
+        &self.pack_name
+    }
+
+    /// Sample of the judgement, else the kind's default sample. Kinds without any sample fall back to taps, except
+    /// misses which stay silent.
+    fn sound_effect(&self, kind: HitsoundKind, judgement: Judgement) -> Option<usize> {
+        let kind_sound_effect = |kind| {
+            self.sound_effects
+                .get(&(kind, SampleJudgement::from(judgement)))
+                .or_else(|| self.sound_effects.get(&(kind, SampleJudgement::Default)))
+                .copied()
+        };
+
+        kind_sound_effect(kind).or_else(|| match kind {
+            HitsoundKind::Tap | HitsoundKind::Miss => None,
+            _ => kind_sound_effect(HitsoundKind::Tap),
+        })
+    }
+
+    /// Returns whether a sample was played, kinds may have no sample.
+    pub(crate) fn play(&self, kind: HitsoundKind, judgement: Judgement) -> Result<bool> {
+        let Some(sound_effect_id) = self.sound_effect(kind, judgement) else {
+            return Ok(false);
+        };
+        self.audio_system.play_sound_effect(sound_effect_id)?;
+        Ok(true)
+    }
+}
+
+impl Drop for HitsoundPack {
+    fn drop(&mut self) {
+        let mut sound_effect_ids = self.sound_effects.values().copied().collect::<Vec<_>>();
+        sound_effect_ids.sort();
+        sound_effect_ids.dedup();
+        for sound_effect_id in sound_effect_ids {
+            self.audio_system.unload_sound_effect(sound_effect_id);
+        }
+    }
+}
+
+/// Names of the packs in the packs directory, sorted.
+pub(crate) fn find_hitsound_packs() -> Result<Vec<String>> {
+    let mut pack_names = std::fs::read_dir(HITSOUND_PACKS_DIRECTORY)?
+        .flatten()
+        .filter(|entry| entry.path().join(MANIFEST_FILE_NAME).is_file())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect::<Vec<_>>();
+    pack_names.sort();
+
+    Ok(pack_names)
+}
@@ -1,21 +1,50 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
 
 use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use winit::{
-    event::{ElementState, MouseButton, WindowEvent},
+    event::{DeviceEvent, ElementState, Touch, TouchPhase, WindowEvent},
     keyboard::{KeyCode as WinitKeyCode, PhysicalKey},
 };
 
-use super::audio::*;
+pub(crate) use chizumu::game::input::{RhythmControlEvent, RhythmControlInput};
+
+use super::{
+    keybindings::{Binding, GamepadButtonCode, Keybindings},
+    mouse_axis::MouseAxis,
+    settings::{DebounceSettings, Settings},
+    touch::{self, TouchLayout, TouchTarget, LANE_HALF_WIDTH},
+};
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
-enum KeyCode {
+/// Physical keys that can be bound, named by their position on a US layout.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum KeyCode {
+    // Menu and editing keys.
     Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Space,
+
+    // Arrow keys.
     Left,
     Up,
     Right,
     Down,
-    Any,
+
+    // Modifiers.
+    ShiftLeft,
+    ShiftRight,
+    ControlLeft,
+    ControlRight,
+    AltLeft,
+    AltRight,
+
+    // Letters.
     A,
     B,
     C,
@@ -42,13 +71,72 @@ enum KeyCode {
     X,
     Y,
     Z,
-    Space,
+
+    // Digits of the main row.
+    Digit0,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+
+    // Numpad.
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadEnter,
+
+    // Punctuation.
     Comma,
+    Period,
+    Semicolon,
+    Quote,
+    Slash,
+    Backslash,
+    BracketLeft,
+    BracketRight,
+    Minus,
+    Equal,
+    Backquote,
+
+    /// Any key without a variant of its own.
+    Any,
 }
 
 impl From<&PhysicalKey> for KeyCode {
     fn from(key: &PhysicalKey) -> Self {
         match key {
+            PhysicalKey::Code(WinitKeyCode::Escape) => KeyCode::Escape,
+            PhysicalKey::Code(WinitKeyCode::Enter) => KeyCode::Enter,
+            PhysicalKey::Code(WinitKeyCode::Tab) => KeyCode::Tab,
+            PhysicalKey::Code(WinitKeyCode::Backspace) => KeyCode::Backspace,
+            PhysicalKey::Code(WinitKeyCode::Space) => KeyCode::Space,
+            PhysicalKey::Code(WinitKeyCode::ArrowLeft) => KeyCode::Left,
+            PhysicalKey::Code(WinitKeyCode::ArrowUp) => KeyCode::Up,
+            PhysicalKey::Code(WinitKeyCode::ArrowRight) => KeyCode::Right,
+            PhysicalKey::Code(WinitKeyCode::ArrowDown) => KeyCode::Down,
+            PhysicalKey::Code(WinitKeyCode::ShiftLeft) => KeyCode::ShiftLeft,
+            PhysicalKey::Code(WinitKeyCode::ShiftRight) => KeyCode::ShiftRight,
+            PhysicalKey::Code(WinitKeyCode::ControlLeft) => KeyCode::ControlLeft,
+            PhysicalKey::Code(WinitKeyCode::ControlRight) => KeyCode::ControlRight,
+            PhysicalKey::Code(WinitKeyCode::AltLeft) => KeyCode::AltLeft,
+            PhysicalKey::Code(WinitKeyCode::AltRight) => KeyCode::AltRight,
             PhysicalKey::Code(WinitKeyCode::KeyA) => KeyCode::A,
             PhysicalKey::Code(WinitKeyCode::KeyB) => KeyCode::B,
             PhysicalKey::Code(WinitKeyCode::KeyC) => KeyCode::C,
@@ -75,22 +163,79 @@ impl From<&PhysicalKey> for KeyCode {
             PhysicalKey::Code(WinitKeyCode::KeyX) => KeyCode::X,
             PhysicalKey::Code(WinitKeyCode::KeyY) => KeyCode::Y,
             PhysicalKey::Code(WinitKeyCode::KeyZ) => KeyCode::Z,
+            PhysicalKey::Code(WinitKeyCode::Digit0) => KeyCode::Digit0,
+            PhysicalKey::Code(WinitKeyCode::Digit1) => KeyCode::Digit1,
+            PhysicalKey::Code(WinitKeyCode::Digit2) => KeyCode::Digit2,
+            PhysicalKey::Code(WinitKeyCode::Digit3) => KeyCode::Digit3,
+            PhysicalKey::Code(WinitKeyCode::Digit4) => KeyCode::Digit4,
+            PhysicalKey::Code(WinitKeyCode::Digit5) => KeyCode::Digit5,
+            PhysicalKey::Code(WinitKeyCode::Digit6) => KeyCode::Digit6,
+            PhysicalKey::Code(WinitKeyCode::Digit7) => KeyCode::Digit7,
+            PhysicalKey::Code(WinitKeyCode::Digit8) => KeyCode::Digit8,
+            PhysicalKey::Code(WinitKeyCode::Digit9) => KeyCode::Digit9,
+            PhysicalKey::Code(WinitKeyCode::Numpad0) => KeyCode::Numpad0,
+            PhysicalKey::Code(WinitKeyCode::Numpad1) => KeyCode::Numpad1,
+            PhysicalKey::Code(WinitKeyCode::Numpad2) => KeyCode::Numpad2,
+            PhysicalKey::Code(WinitKeyCode::Numpad3) => KeyCode::Numpad3,
+            PhysicalKey::Code(WinitKeyCode::Numpad4) => KeyCode::Numpad4,
+            PhysicalKey::Code(WinitKeyCode::Numpad5) => KeyCode::Numpad5,
+            PhysicalKey::Code(WinitKeyCode::Numpad6) => KeyCode::Numpad6,
+            PhysicalKey::Code(WinitKeyCode::Numpad7) => KeyCode::Numpad7,
+            PhysicalKey::Code(WinitKeyCode::Numpad8) => KeyCode::Numpad8,
+            PhysicalKey::Code(WinitKeyCode::Numpad9) => KeyCode::Numpad9,
+            PhysicalKey::Code(WinitKeyCode::NumpadAdd) => KeyCode::NumpadAdd,
+            PhysicalKey::Code(WinitKeyCode::NumpadSubtract) => KeyCode::NumpadSubtract,
+            PhysicalKey::Code(WinitKeyCode::NumpadMultiply) => KeyCode::NumpadMultiply,
+            PhysicalKey::Code(WinitKeyCode::NumpadDivide) => KeyCode::NumpadDivide,
+            PhysicalKey::Code(WinitKeyCode::NumpadDecimal) => KeyCode::NumpadDecimal,
+            PhysicalKey::Code(WinitKeyCode::NumpadEnter) => KeyCode::NumpadEnter,
             PhysicalKey::Code(WinitKeyCode::Comma) => KeyCode::Comma,
-            PhysicalKey::Code(WinitKeyCode::Space) => KeyCode::Space,
+            PhysicalKey::Code(WinitKeyCode::Period) => KeyCode::Period,
+            PhysicalKey::Code(WinitKeyCode::Semicolon) => KeyCode::Semicolon,
+            PhysicalKey::Code(WinitKeyCode::Quote) => KeyCode::Quote,
+            PhysicalKey::Code(WinitKeyCode::Slash) => KeyCode::Slash,
+            PhysicalKey::Code(WinitKeyCode::Backslash) => KeyCode::Backslash,
+            PhysicalKey::Code(WinitKeyCode::BracketLeft) => KeyCode::BracketLeft,
+            PhysicalKey::Code(WinitKeyCode::BracketRight) => KeyCode::BracketRight,
+            PhysicalKey::Code(WinitKeyCode::Minus) => KeyCode::Minus,
+            PhysicalKey::Code(WinitKeyCode::Equal) => KeyCode::Equal,
+            PhysicalKey::Code(WinitKeyCode::Backquote) => KeyCode::Backquote,
             _ => KeyCode::Any,
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
-enum RhythmControlInput {
-    Tap1,
-    Tap2,
-    Tap3,
-    Tap4,
-    TapMove1,
-    TapMove2,
-    TapWide,
+/// When an input happened, as precisely as the platform reports it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InputTimestamps {
+    /// When the OS received the event, `None` if the platform does not report it.
+    pub(crate) os_event: Option<Instant>,
+    /// When the input handler received the event.
+    pub(crate) received: Instant,
+}
+
+impl InputTimestamps {
+    /// Timestamps of an event without an OS timestamp received just now.
+    pub(crate) fn now() -> Self {
+        Self {
+            os_event: None,
+            received: Instant::now(),
+        }
+    }
+
+    /// The earliest known instant of the input.
+    pub(crate) fn input_instant(&self) -> Instant {
+        self.os_event.unwrap_or(self.received)
+    }
+}
+
+/// An event with the instants it was received at, to be mapped to song time by the conductor.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimestampedRhythmControlEvent {
+    pub(crate) event: RhythmControlEvent,
+    pub(crate) timestamps: InputTimestamps,
+    /// Leftmost cell and number of cells covered by the input, `None` if the input is not tied to cells.
+    pub(crate) cells: Option<(u32, u32)>,
 }
 
 enum RhythmControlInputState {
@@ -128,63 +273,472 @@ impl RhythmControlState {
 }
 
 pub(crate) struct RhythmControlInputHandler {
-    rhythm_control_keybindings: HashMap<KeyCode, RhythmControlInput>,
+    /// Swapped out when the keybindings change.
+    rhythm_control_keybindings: RwLock<HashMap<Binding, RhythmControlInput>>,
+    /// Cells of each key of the lane layout, looked up before the other bindings. Empty while the layout is off.
+    lane_keybindings: RwLock<HashMap<Binding, (u32, u32)>>,
+    /// Keys of the lane layout currently held down and the cells they pressed.
+    held_lane_keys: Mutex<HashMap<Binding, (u32, u32)>>,
 
-    /// XXX: Maybe an RwLock is better here.
     rhythm_control_state: Mutex<RhythmControlState>,
+    /// Sources currently held down and the input they pressed, an input stays pressed while any of its sources
+    /// is held.
+    held_sources: Mutex<HashMap<InputSource, RhythmControlInput>>,
+
+    /// Press and release events not yet consumed by the game, in order of arrival.
+    pending_events: Mutex<Vec<PendingEvent>>,
+
+    debounce: RwLock<DebounceSettings>,
+    /// Release and press pairs discarded as key chatter, not yet taken by the diagnostics.
+    debounced_chatter: Mutex<Vec<DebouncedChatter>>,
+
+    /// Bindings pressed while capturing for rebinding, they are not handled as inputs. `None` while not capturing.
+    binding_capture: Mutex<Option<Vec<Binding>>>,
 
-    /// XXX: Use an existing audio system to properly mix with music sound(?)
-    audio_system: Mutex<AudioSystem>,
+    touch_layout: RwLock<TouchLayout>,
+    /// Touches currently down, by touch id.
+    active_touches: Mutex<HashMap<u64, ActiveTouch>>,
+    /// Lateral position of the latest touch on the platform, not yet taken by the game.
+    touch_steering: Mutex<Option<f32>>,
+
+    /// Aims presses of move inputs from mouse buttons at the cell under it.
+    mouse_axis: Mutex<MouseAxis>,
 }
 
-impl RhythmControlInputHandler {
-    pub(crate) fn new() -> Self {
-        let mut rhythm_control_keybindings = HashMap::new();
+struct PendingEvent {
+    event: TimestampedRhythmControlEvent,
+    /// Releases from physical sources are held back until then, a press of the same input before then discards
+    /// both as key chatter.
+    held_back_until: Option<Instant>,
+}
+
+/// A release and press pair of an input discarded as key chatter.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DebouncedChatter {
+    pub(crate) input: RhythmControlInput,
+    /// Time between the release and the press.
+    pub(crate) gap: Duration,
+}
+
+/// Touches stay on what they first landed on, lanes or platform, until they are lifted.
+enum ActiveTouch {
+    Lanes {
+        cell: u32,
+        /// Leftmost and rightmost cells visited while sliding.
+        visited_cells: (u32, u32),
+    },
+    Platform,
+}
 
-        rhythm_control_keybindings.insert(KeyCode::Q, RhythmControlInput::Tap1);
-        rhythm_control_keybindings.insert(KeyCode::W, RhythmControlInput::Tap2);
-        rhythm_control_keybindings.insert(KeyCode::E, RhythmControlInput::Tap3);
-        rhythm_control_keybindings.insert(KeyCode::R, RhythmControlInput::Tap4);
-        rhythm_control_keybindings.insert(KeyCode::Space, RhythmControlInput::TapWide);
+/// Physical source of a press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InputSource {
+    Binding(Binding),
+    GamepadButton {
+        gamepad_id: usize,
+        button: GamepadButtonCode,
+    },
+}
 
+impl RhythmControlInputHandler {
+    pub(crate) fn new(
+        keybindings: &Keybindings,
+        settings: &Settings,
+        touch_layout: TouchLayout,
+    ) -> Self {
         Self {
-            rhythm_control_keybindings,
+            rhythm_control_keybindings: RwLock::new(keybindings.input_map()),
+            lane_keybindings: RwLock::new(settings.lane_layout().cell_map()),
+            held_lane_keys: Mutex::new(HashMap::new()),
             rhythm_control_state: Mutex::new(RhythmControlState::new()),
-            audio_system: Mutex::new(AudioSystem::new().unwrap()),
+            held_sources: Mutex::new(HashMap::new()),
+            pending_events: Mutex::new(Vec::new()),
+            debounce: RwLock::new(settings.debounce().clone()),
+            debounced_chatter: Mutex::new(Vec::new()),
+            binding_capture: Mutex::new(None),
+            touch_layout: RwLock::new(touch_layout),
+            active_touches: Mutex::new(HashMap::new()),
+            touch_steering: Mutex::new(None),
+            mouse_axis: Mutex::new(MouseAxis::new(settings.mouse_sensitivity())),
         }
     }
 
+    pub(crate) fn set_keybindings(&self, keybindings: &Keybindings) {
+        *self.rhythm_control_keybindings.write() = keybindings.input_map();
+    }
+
+    pub(crate) fn set_settings(&self, settings: &Settings) {
+        *self.lane_keybindings.write() = settings.lane_layout().cell_map();
+        *self.debounce.write() = settings.debounce().clone();
+        self.mouse_axis
+            .lock()
+            .set_sensitivity(settings.mouse_sensitivity());
+    }
+
+    pub(crate) fn handle_device_event(&self, device_event: &DeviceEvent) {
+        self.mouse_axis.lock().handle_device_event(device_event);
+    }
+
+    /// Position of the mouse axis in [-1, 1] if the mouse moved since the last call.
+    pub(crate) fn sample_mouse_axis(&self) -> Option<f32> {
+        self.mouse_axis.lock().sample()
+    }
+
+    /// Drops every queued event and releases everything held without queueing releases, e.g. when a chart is
+    /// loaded so events from before it are not judged on it.
+    pub(crate) fn reset(&self) {
+        // Locked in the same order as when events are queued.
+        let mut held_sources = self.held_sources.lock();
+        let mut held_lane_keys = self.held_lane_keys.lock();
+        let mut control_state = self.rhythm_control_state.lock();
+        let mut pending_events = self.pending_events.lock();
+        held_sources.clear();
+        held_lane_keys.clear();
+        *control_state = RhythmControlState::new();
+        pending_events.clear();
+
+        self.debounced_chatter.lock().clear();
+        self.active_touches.lock().clear();
+        *self.touch_steering.lock() = None;
+        self.mouse_axis.lock().reset();
+    }
+
+    /// Cell under the mouse axis, the whole axis range is spread over the lanes independently of the steering range
+    /// so every cell can be aimed at.
+    fn mouse_axis_cell(&self) -> u32 {
+        let lateral_position = (self.mouse_axis.lock().position() * LANE_HALF_WIDTH)
+            .clamp(-LANE_HALF_WIDTH, LANE_HALF_WIDTH - f32::EPSILON);
+        touch::lane_cell(lateral_position).unwrap_or(0)
+    }
+
+    /// Window events carry no timestamp, they are stamped as they are handled.
     pub(crate) fn handle_window_event(&self, window_event: &WindowEvent) {
+        let timestamps = InputTimestamps::now();
         match window_event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                self.handle_keyboard_input(KeyCode::from(&event.physical_key), event.state)
-            }
+            WindowEvent::KeyboardInput { event, .. } => self.handle_binding_input(
+                Binding::Key(KeyCode::from(&event.physical_key)),
+                event.state,
+                timestamps,
+            ),
             WindowEvent::MouseInput { button, state, .. } => {
-                self.handle_mouse_input(&button, *state)
+                if let Some(binding) = Binding::from_mouse_button(button) {
+                    self.handle_binding_input(binding, *state, timestamps);
+                }
             }
+            WindowEvent::Touch(touch) => self.handle_touch(touch, timestamps),
+            WindowEvent::Resized(size) => self.touch_layout.write().set_window_size(*size),
             _ => {}
         }
     }
 
-    fn handle_keyboard_input(&self, keycode: KeyCode, state: ElementState) {
-        if let Some(control_input) = self.rhythm_control_keybindings.get(&keycode) {
-            self.update_rhythm_control_state(*control_input, state);
+    /// A touch on the lanes presses the cell under it and hits further cells as it slides over them, it is
+    /// released over every cell it visited so holds end with the touch wherever it was lifted.
+    /// A touch on the platform steers the runner.
+    fn handle_touch(&self, touch: &Touch, timestamps: InputTimestamps) {
+        let target = self.touch_layout.read().target(touch.location);
+        let mut active_touches = self.active_touches.lock();
+
+        match touch.phase {
+            TouchPhase::Started => match target {
+                Some(TouchTarget::Cell(cell)) => {
+                    active_touches.insert(
+                        touch.id,
+                        ActiveTouch::Lanes {
+                            cell,
+                            visited_cells: (cell, cell),
+                        },
+                    );
+                    self.queue_event(RhythmControlInput::Touch, true, timestamps, Some((cell, 1)));
+                }
+                Some(TouchTarget::Platform(lateral_position)) => {
+                    active_touches.insert(touch.id, ActiveTouch::Platform);
+                    *self.touch_steering.lock() = Some(lateral_position);
+                }
+                None => {}
+            },
+            TouchPhase::Moved => match (active_touches.get_mut(&touch.id), target) {
+                (
+                    Some(ActiveTouch::Lanes {
+                        cell,
+                        visited_cells,
+                    }),
+                    Some(TouchTarget::Cell(new_cell)),
+                ) if *cell != new_cell => {
+                    *cell = new_cell;
+                    *visited_cells = (visited_cells.0.min(new_cell), visited_cells.1.max(new_cell));
+                    self.queue_event(
+                        RhythmControlInput::Touch,
+                        true,
+                        timestamps,
+                        Some((new_cell, 1)),
+                    );
+                }
+                (Some(ActiveTouch::Platform), Some(TouchTarget::Platform(lateral_position))) => {
+                    *self.touch_steering.lock() = Some(lateral_position);
+                }
+                _ => {}
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                if let Some(ActiveTouch::Lanes {
+                    visited_cells: (first_cell, last_cell),
+                    ..
+                }) = active_touches.remove(&touch.id)
+                {
+                    self.queue_event(
+                        RhythmControlInput::Touch,
+                        false,
+                        timestamps,
+                        Some((first_cell, last_cell - first_cell + 1)),
+                    );
+                }
+            }
         }
     }
 
-    fn handle_mouse_input(&self, button: &MouseButton, state: ElementState) {
-        let control_input = match button {
-            MouseButton::Left => Some(RhythmControlInput::TapMove1),
-            MouseButton::Right => Some(RhythmControlInput::TapMove2),
+    /// Lateral position of the latest touch on the platform since the last call.
+    pub(crate) fn take_touch_steering(&self) -> Option<f32> {
+        self.touch_steering.lock().take()
+    }
+
+    fn handle_binding_input(
+        &self,
+        binding: Binding,
+        state: ElementState,
+        timestamps: InputTimestamps,
+    ) {
+        if state == ElementState::Pressed && !binding.is_reserved() {
+            if let Some(captured_bindings) = self.binding_capture.lock().as_mut() {
+                captured_bindings.push(binding);
+                return;
+            }
+        }
+
+        if let Some(cells) = self.lane_keybindings.read().get(&binding).copied() {
+            self.handle_lane_key(binding, cells, state, timestamps);
+            return;
+        }
+
+        let control_input = self
+            .rhythm_control_keybindings
+            .read()
+            .get(&binding)
+            .copied();
+
+        // Move notes follow the mouse, presses of move inputs from mouse buttons only hit notes under the axis.
+        let cells = match (binding, control_input) {
+            (
+                Binding::Mouse(_),
+                Some(RhythmControlInput::TapMove1 | RhythmControlInput::TapMove2),
+            ) if state == ElementState::Pressed => Some((self.mouse_axis_cell(), 1)),
             _ => None,
         };
 
-        if let Some(control_input) = control_input {
-            self.update_rhythm_control_state(control_input, state);
+        self.handle_source_input(
+            InputSource::Binding(binding),
+            control_input,
+            state,
+            timestamps,
+            cells,
+        );
+    }
+
+    /// Keys of the lane layout are pressed and released independently of each other, each with the cells of its
+    /// region.
+    fn handle_lane_key(
+        &self,
+        binding: Binding,
+        cells: (u32, u32),
+        state: ElementState,
+        timestamps: InputTimestamps,
+    ) {
+        let mut held_lane_keys = self.held_lane_keys.lock();
+        match state {
+            ElementState::Pressed => {
+                // Key repeats are ignored.
+                if held_lane_keys.insert(binding, cells).is_none()
+                    && !self.discard_chatter(RhythmControlInput::Lane, timestamps, Some(cells))
+                {
+                    self.queue_event(RhythmControlInput::Lane, true, timestamps, Some(cells));
+                }
+            }
+            ElementState::Released => {
+                // Released with the cells it pressed, the layout may have changed since.
+                if let Some(cells) = held_lane_keys.remove(&binding) {
+                    self.queue_event(RhythmControlInput::Lane, false, timestamps, Some(cells));
+                    self.hold_back_release(RhythmControlInput::Lane, timestamps);
+                }
+            }
+        }
+    }
+
+    /// `control_input` - input the button is bound to in the gamepad's profile.
+    /// `os_event` - when the OS received the button event.
+    pub(crate) fn handle_gamepad_button(
+        &self,
+        gamepad_id: usize,
+        button: GamepadButtonCode,
+        control_input: Option<RhythmControlInput>,
+        state: ElementState,
+        os_event: Instant,
+    ) {
+        self.handle_source_input(
+            InputSource::GamepadButton { gamepad_id, button },
+            control_input,
+            state,
+            InputTimestamps {
+                os_event: Some(os_event),
+                received: Instant::now(),
+            },
+            None,
+        );
+    }
+
+    /// Releases every button still held on the gamepad, e.g. when it is disconnected.
+    pub(crate) fn release_gamepad(&self, gamepad_id: usize, os_event: Instant) {
+        let held_buttons = self
+            .held_sources
+            .lock()
+            .keys()
+            .filter_map(|source| match *source {
+                InputSource::GamepadButton {
+                    gamepad_id: held_gamepad_id,
+                    button,
+                } if held_gamepad_id == gamepad_id => Some(button),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for button in held_buttons {
+            self.handle_gamepad_button(gamepad_id, button, None, ElementState::Released, os_event);
+        }
+    }
+
+    /// `cells` - cells a press is aimed at, `None` if it is not tied to cells.
+    fn handle_source_input(
+        &self,
+        source: InputSource,
+        control_input: Option<RhythmControlInput>,
+        state: ElementState,
+        timestamps: InputTimestamps,
+        cells: Option<(u32, u32)>,
+    ) {
+        let mut held_sources = self.held_sources.lock();
+        match state {
+            ElementState::Pressed => {
+                if let Some(control_input) = control_input {
+                    held_sources.insert(source, control_input);
+                    if !self.discard_chatter(control_input, timestamps, cells) {
+                        self.update_rhythm_control_state(control_input, state, timestamps, cells);
+                    }
+                }
+            }
+            ElementState::Released => {
+                // Released as the input it pressed, the bindings may have changed since.
+                let Some(control_input) = held_sources.remove(&source) else {
+                    return;
+                };
+                if !held_sources.values().any(|input| *input == control_input) {
+                    self.update_rhythm_control_state(control_input, state, timestamps, None);
+                    self.hold_back_release(control_input, timestamps);
+                }
+            }
         }
     }
 
-    fn update_rhythm_control_state(&self, control_input: RhythmControlInput, state: ElementState) {
+    /// Holds back the release just queued for the input by its debounce threshold.
+    fn hold_back_release(&self, control_input: RhythmControlInput, timestamps: InputTimestamps) {
+        let threshold = self.debounce.read().threshold(control_input);
+        if threshold.is_zero() {
+            return;
+        }
+
+        if let Some(pending_event) = self
+            .pending_events
+            .lock()
+            .last_mut()
+            .filter(|pending_event| {
+                pending_event.event.event.input == control_input
+                    && !pending_event.event.event.pressed
+                    && pending_event.event.timestamps.received == timestamps.received
+            })
+        {
+            pending_event.held_back_until = Some(timestamps.input_instant() + threshold);
+        }
+    }
+
+    /// Discards a press together with the held back release of the same input it follows within the debounce
+    /// threshold, the input stays pressed as if it was never released.
+    /// Releases tied to cells only pair with presses of the same cells.
+    /// Returns whether the press was discarded.
+    fn discard_chatter(
+        &self,
+        control_input: RhythmControlInput,
+        timestamps: InputTimestamps,
+        cells: Option<(u32, u32)>,
+    ) -> bool {
+        let threshold = self.debounce.read().threshold(control_input);
+
+        // Locked in the same order as when events are queued.
+        let mut control_state = self.rhythm_control_state.lock();
+        let mut pending_events = self.pending_events.lock();
+
+        let Some(release_index) = pending_events.iter().rposition(|pending_event| {
+            pending_event.held_back_until.is_some()
+                && pending_event.event.event.input == control_input
+                && !pending_event.event.event.pressed
+                && pending_event
+                    .event
+                    .cells
+                    .map_or(true, |release_cells| Some(release_cells) == cells)
+        }) else {
+            return false;
+        };
+        let gap = timestamps.input_instant().saturating_duration_since(
+            pending_events[release_index]
+                .event
+                .timestamps
+                .input_instant(),
+        );
+        if gap > threshold {
+            return false;
+        }
+
+        pending_events.remove(release_index);
+        if let Some(input_state) = control_state.states.get_mut(&control_input) {
+            *input_state = RhythmControlInputState::Pressed;
+        }
+        self.debounced_chatter.lock().push(DebouncedChatter {
+            input: control_input,
+            gap,
+        });
+
+        true
+    }
+
+    /// Captures every pressed key or mouse button that is not reserved until the capture is ended, see
+    /// `take_captured_bindings`.
+    pub(crate) fn start_binding_capture(&self) {
+        *self.binding_capture.lock() = Some(Vec::new());
+    }
+
+    pub(crate) fn end_binding_capture(&self) {
+        *self.binding_capture.lock() = None;
+    }
+
+    /// Bindings pressed since the last call, in order, the capture keeps running.
+    pub(crate) fn take_captured_bindings(&self) -> Vec<Binding> {
+        self.binding_capture
+            .lock()
+            .as_mut()
+            .map_or_else(Vec::new, std::mem::take)
+    }
+
+    fn update_rhythm_control_state(
+        &self,
+        control_input: RhythmControlInput,
+        state: ElementState,
+        timestamps: InputTimestamps,
+        cells: Option<(u32, u32)>,
+    ) {
         let mut control_state = self.rhythm_control_state.lock();
         match state {
             ElementState::Pressed => {
@@ -195,28 +749,75 @@ impl RhythmControlInputHandler {
                         }
                         RhythmControlInputState::Unpressed => {
                             // Unpressed -> pressed.
-                            self.play_tap_sound(control_input);
                             control_state
                                 .states
                                 .insert(control_input, RhythmControlInputState::Pressed);
+                            self.queue_event(control_input, true, timestamps, cells);
                         }
                     }
                 }
             }
             ElementState::Released => {
-                control_state
+                if let Some(RhythmControlInputState::Pressed) = control_state
                     .states
-                    .insert(control_input, RhythmControlInputState::Unpressed);
+                    .insert(control_input, RhythmControlInputState::Unpressed)
+                {
+                    self.queue_event(control_input, false, timestamps, None);
+                }
             }
         }
     }
 
-    /// XXX: Figure out the best way to play these tap sounds as fast as possible, want minimum latency between press -> sound.
-    fn play_tap_sound(&self, rhythm_control: RhythmControlInput) {
-        match rhythm_control {
-            _ => {
-                self.audio_system.lock().play_sound_effect(0).unwrap();
-            }
-        }
+    fn queue_event(
+        &self,
+        input: RhythmControlInput,
+        pressed: bool,
+        timestamps: InputTimestamps,
+        cells: Option<(u32, u32)>,
+    ) {
+        self.pending_events.lock().push(PendingEvent {
+            event: TimestampedRhythmControlEvent {
+                event: RhythmControlEvent { input, pressed },
+                timestamps,
+                cells,
+            },
+            held_back_until: None,
+        });
+    }
+
+    /// Takes the press and release events received since the last call, in order of arrival.
+    /// Events stay queued behind a release that is still held back for debouncing, they keep their timestamps, see
+    /// `earliest_queued_instant`.
+    pub(crate) fn drain_events(&self) -> Vec<TimestampedRhythmControlEvent> {
+        let now = Instant::now();
+        let mut pending_events = self.pending_events.lock();
+        let ready_count = pending_events
+            .iter()
+            .position(|pending_event| {
+                pending_event
+                    .held_back_until
+                    .is_some_and(|held_back_until| held_back_until > now)
+            })
+            .unwrap_or(pending_events.len());
+
+        pending_events
+            .drain(..ready_count)
+            .map(|pending_event| pending_event.event)
+            .collect()
+    }
+
+    /// Input instant of the earliest event still queued, e.g. behind a release held back for debouncing.
+    /// The game should not be updated past it, the event is judged at its own time once it is taken.
+    pub(crate) fn earliest_queued_instant(&self) -> Option<Instant> {
+        self.pending_events
+            .lock()
+            .iter()
+            .map(|pending_event| pending_event.event.timestamps.input_instant())
+            .min()
+    }
+
+    /// Takes the release and press pairs discarded as key chatter since the last call.
+    pub(crate) fn drain_debounced_chatter(&self) -> Vec<DebouncedChatter> {
+        std::mem::take(&mut *self.debounced_chatter.lock())
     }
 }
@@ -0,0 +1,341 @@
+/*!
+ * Bindings of physical keys, mouse buttons and gamepad buttons to rhythm control inputs, kept as a JSON file in the
+ * user's config directory.
+ * Gamepads are bound through profiles per device, profiles are edited in the file.
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use winit::event::MouseButton;
+
+use super::input::{KeyCode, RhythmControlInput};
+
+const KEYBINDINGS_FILE_NAME: &str = "keybindings.json";
+
+/// Keys used by menus and steering, they can not be bound.
+const RESERVED_KEYS: &[KeyCode] = &[
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Any,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum MouseButtonCode {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+/// A physical key or mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButtonCode),
+}
+
+impl Binding {
+    pub(crate) fn from_mouse_button(button: &MouseButton) -> Option<Self> {
+        let button = match button {
+            MouseButton::Left => MouseButtonCode::Left,
+            MouseButton::Right => MouseButtonCode::Right,
+            MouseButton::Middle => MouseButtonCode::Middle,
+            MouseButton::Back => MouseButtonCode::Back,
+            MouseButton::Forward => MouseButtonCode::Forward,
+            MouseButton::Other(_) => return None,
+        };
+        Some(Binding::Mouse(button))
+    }
+
+    pub(crate) fn is_reserved(&self) -> bool {
+        match self {
+            Binding::Key(keycode) => RESERVED_KEYS.contains(keycode),
+            Binding::Mouse(_) => false,
+        }
+    }
+}
+
+/// Gamepad buttons, named by their position on a standard layout. Analog triggers act as buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum GamepadButtonCode {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    /// Extra buttons of arcade controllers.
+    C,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum GamepadAxisCode {
+    LeftStickX,
+    RightStickX,
+}
+
+/// Bindings of a gamepad, a button belongs to at most one input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct GamepadProfile {
+    pub(crate) buttons: BTreeMap<RhythmControlInput, Vec<GamepadButtonCode>>,
+    pub(crate) steering_axis: GamepadAxisCode,
+    pub(crate) invert_steering: bool,
+}
+
+impl Default for GamepadProfile {
+    fn default() -> Self {
+        let mut buttons = BTreeMap::<RhythmControlInput, Vec<GamepadButtonCode>>::new();
+        let defaults = [
+            (RhythmControlInput::Tap1, GamepadButtonCode::West),
+            (RhythmControlInput::Tap2, GamepadButtonCode::North),
+            (RhythmControlInput::Tap3, GamepadButtonCode::South),
+            (RhythmControlInput::Tap4, GamepadButtonCode::East),
+            (RhythmControlInput::TapMove1, GamepadButtonCode::LeftTrigger),
+            (
+                RhythmControlInput::TapMove2,
+                GamepadButtonCode::RightTrigger,
+            ),
+            (RhythmControlInput::TapWide, GamepadButtonCode::LeftBumper),
+            (RhythmControlInput::TapWide, GamepadButtonCode::RightBumper),
+        ];
+        for (input, button) in defaults {
+            buttons.entry(input).or_default().push(button);
+        }
+
+        Self {
+            buttons,
+            steering_axis: GamepadAxisCode::LeftStickX,
+            invert_steering: false,
+        }
+    }
+}
+
+impl GamepadProfile {
+    /// Input of each button, for looking up gamepad events.
+    pub(crate) fn input_map(&self) -> HashMap<GamepadButtonCode, RhythmControlInput> {
+        self.buttons
+            .iter()
+            .flat_map(|(input, buttons)| buttons.iter().map(|button| (*button, *input)))
+            .collect()
+    }
+
+    fn conflicting_buttons(&self) -> Vec<GamepadButtonCode> {
+        let mut buttons = self.buttons.values().flatten().copied().collect::<Vec<_>>();
+        buttons.sort();
+        let mut conflicting_buttons = buttons
+            .windows(2)
+            .filter(|pair| pair[0] == pair[1])
+            .map(|pair| pair[0])
+            .collect::<Vec<_>>();
+        conflicting_buttons.dedup();
+        conflicting_buttons
+    }
+}
+
+/// A binding that is bound to more than one input.
+#[derive(Debug, Clone)]
+pub(crate) struct BindingConflict {
+    pub(crate) binding: Binding,
+    pub(crate) inputs: Vec<RhythmControlInput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Keybindings {
+    /// Any number of bindings per input, a binding belongs to at most one input.
+    bindings: BTreeMap<RhythmControlInput, Vec<Binding>>,
+    /// Used for gamepads without a profile of their own.
+    #[serde(default)]
+    default_gamepad_profile: GamepadProfile,
+    /// Keyed by gamepad UUID.
+    #[serde(default)]
+    gamepad_profiles: BTreeMap<String, GamepadProfile>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let mut keybindings = Self {
+            bindings: BTreeMap::new(),
+            default_gamepad_profile: GamepadProfile::default(),
+            gamepad_profiles: BTreeMap::new(),
+        };
+
+        let defaults = [
+            (RhythmControlInput::Tap1, Binding::Key(KeyCode::Q)),
+            (RhythmControlInput::Tap2, Binding::Key(KeyCode::W)),
+            (RhythmControlInput::Tap3, Binding::Key(KeyCode::E)),
+            (RhythmControlInput::Tap4, Binding::Key(KeyCode::R)),
+            (RhythmControlInput::TapWide, Binding::Key(KeyCode::Space)),
+            (
+                RhythmControlInput::TapMove1,
+                Binding::Mouse(MouseButtonCode::Left),
+            ),
+            (
+                RhythmControlInput::TapMove2,
+                Binding::Mouse(MouseButtonCode::Right),
+            ),
+        ];
+        for (input, binding) in defaults {
+            keybindings.bindings.entry(input).or_default().push(binding);
+        }
+
+        keybindings
+    }
+}
+
+impl Keybindings {
+    /// Path of the keybindings file in the user's config directory.
+    pub(crate) fn default_file_path() -> Result<PathBuf> {
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow!("No user config directory available"))?;
+        Ok(config_dir.join("chizumu").join(KEYBINDINGS_FILE_NAME))
+    }
+
+    /// Default bindings if the file does not exist yet.
+    pub(crate) fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let keybindings: Self = serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+        keybindings.validate()?;
+        Ok(keybindings)
+    }
+
+    /// Writes to a temporary file first so a failed write does not lose the existing bindings.
+    pub(crate) fn save(&self, file_path: &Path) -> Result<()> {
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temp_file_path = file_path.with_extension("json.tmp");
+        std::fs::write(&temp_file_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temp_file_path, file_path)?;
+
+        Ok(())
+    }
+
+    /// Fails on reserved keys and on bindings bound to several inputs, e.g. in a hand edited file.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(binding) = self
+            .bindings
+            .values()
+            .flatten()
+            .find(|binding| binding.is_reserved())
+        {
+            return Err(anyhow!("{:?} is reserved and can not be bound", binding));
+        }
+
+        let conflicts = self
+            .conflicts()
+            .iter()
+            .map(|conflict| format!("{:?} bound to {:?}", conflict.binding, conflict.inputs))
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            return Err(anyhow!("Conflicting keybindings: {}", conflicts.join(", ")));
+        }
+
+        let profiles = std::iter::once(("default", &self.default_gamepad_profile)).chain(
+            self.gamepad_profiles
+                .iter()
+                .map(|(uuid, profile)| (uuid.as_str(), profile)),
+        );
+        for (name, profile) in profiles {
+            let conflicting_buttons = profile.conflicting_buttons();
+            if !conflicting_buttons.is_empty() {
+                return Err(anyhow!(
+                    "Gamepad profile {} binds {:?} to several inputs",
+                    name,
+                    conflicting_buttons
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn conflicts(&self) -> Vec<BindingConflict> {
+        let mut inputs_per_binding = BTreeMap::<Binding, Vec<RhythmControlInput>>::new();
+        for (input, bindings) in &self.bindings {
+            for binding in bindings {
+                inputs_per_binding.entry(*binding).or_default().push(*input);
+            }
+        }
+
+        inputs_per_binding
+            .into_iter()
+            .filter(|(_, inputs)| inputs.len() > 1)
+            .map(|(binding, inputs)| BindingConflict { binding, inputs })
+            .collect()
+    }
+
+    pub(crate) fn bindings(&self, input: RhythmControlInput) -> &[Binding] {
+        self.bindings.get(&input).map_or(&[], |bindings| bindings)
+    }
+
+    /// Adds a binding to the input, taking it away from the input it was bound to before.
+    /// Returns that input, if any.
+    pub(crate) fn bind(
+        &mut self,
+        input: RhythmControlInput,
+        binding: Binding,
+    ) -> Result<Option<RhythmControlInput>> {
+        if binding.is_reserved() {
+            return Err(anyhow!("{:?} is reserved and can not be bound", binding));
+        }
+
+        let mut previous_input = None;
+        for (other_input, bindings) in self.bindings.iter_mut() {
+            if *other_input != input && bindings.contains(&binding) {
+                bindings.retain(|other_binding| *other_binding != binding);
+                previous_input = Some(*other_input);
+            }
+        }
+
+        let bindings = self.bindings.entry(input).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+
+        Ok(previous_input)
+    }
+
+    /// Removes every binding of the input.
+    pub(crate) fn clear(&mut self, input: RhythmControlInput) {
+        self.bindings.remove(&input);
+    }
+
+    /// Profile of the gamepad with the UUID, the default profile if it has none.
+    pub(crate) fn gamepad_profile(&self, uuid: &str) -> &GamepadProfile {
+        self.gamepad_profiles
+            .get(uuid)
+            .unwrap_or(&self.default_gamepad_profile)
+    }
+
+    /// Input of each binding, for looking up physical inputs.
+    pub(crate) fn input_map(&self) -> HashMap<Binding, RhythmControlInput> {
+        self.bindings
+            .iter()
+            .flat_map(|(input, bindings)| bindings.iter().map(|binding| (*binding, *input)))
+            .collect()
+    }
+}
@@ -0,0 +1,208 @@
+/*!
+ * Input latency diagnostics, follows each press from the OS event to the hitsound and the frame it was rendered in,
+ * and reports percentile latencies to compare audio backends and settings.
+ * Key chatter discarded by debouncing is reported along with it.
+ */
+
+use std::{collections::BTreeMap, time::Instant};
+
+use super::input::{
+    DebouncedChatter, InputTimestamps, RhythmControlInput, TimestampedRhythmControlEvent,
+};
+
+const REPORTED_PERCENTILES: [f32; 4] = [50.0, 90.0, 99.0, 100.0];
+
+/// A press followed through the game.
+#[derive(Debug, Clone, Copy)]
+struct LatencySample {
+    /// Id given to the press, carried through its judgement.
+    id: u64,
+    input: RhythmControlInput,
+    timestamps: InputTimestamps,
+    /// When the hitsound of the note hit by the press was scheduled, `None` if the press hit nothing.
+    sound_scheduled: Option<Instant>,
+    /// Index of the first frame rendered after the press was handled and when its rendering was submitted.
+    rendered_frame: Option<(u64, Instant)>,
+}
+
+impl LatencySample {
+    /// Milliseconds from the OS event to the input handler.
+    fn os_to_handler_ms(&self) -> Option<f32> {
+        self.timestamps
+            .os_event
+            .map(|os_event| elapsed_ms(os_event, self.timestamps.received))
+    }
+
+    fn handler_to_sound_ms(&self) -> Option<f32> {
+        self.sound_scheduled
+            .map(|sound_scheduled| elapsed_ms(self.timestamps.received, sound_scheduled))
+    }
+
+    fn handler_to_frame_ms(&self) -> Option<f32> {
+        self.rendered_frame
+            .map(|(_, rendered)| elapsed_ms(self.timestamps.received, rendered))
+    }
+
+    /// Milliseconds from the earliest known instant of the input to the frame.
+    fn input_to_frame_ms(&self) -> Option<f32> {
+        self.rendered_frame
+            .map(|(_, rendered)| elapsed_ms(self.timestamps.input_instant(), rendered))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyDiagnostics {
+    /// Presses handled since the last rendered frame.
+    pending_samples: Vec<LatencySample>,
+    samples: Vec<LatencySample>,
+    frame_index: u64,
+    next_sample_id: u64,
+    debounced_chatter: Vec<DebouncedChatter>,
+}
+
+impl LatencyDiagnostics {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id to give the press's `JudgementInput`, `None` for releases which are not followed as they do
+    /// not play hitsounds.
+    pub(crate) fn record_input(&mut self, event: &TimestampedRhythmControlEvent) -> Option<u64> {
+        if !event.event.pressed {
+            return None;
+        }
+
+        let id = self.next_sample_id;
+        self.next_sample_id += 1;
+        self.pending_samples.push(LatencySample {
+            id,
+            input: event.event.input,
+            timestamps: event.timestamps,
+            sound_scheduled: None,
+            rendered_frame: None,
+        });
+        Some(id)
+    }
+
+    /// Records the hitsound of a note hit by the press with the id returned by `record_input`.
+    pub(crate) fn record_sound_scheduled(&mut self, id: u64, sound_scheduled: Instant) {
+        if let Some(sample) = self
+            .pending_samples
+            .iter_mut()
+            .find(|sample| sample.id == id)
+        {
+            sample.sound_scheduled = Some(sound_scheduled);
+        }
+    }
+
+    /// Completes the pending presses with the frame, should be called once the frame's rendering is submitted.
+    pub(crate) fn record_frame_rendered(&mut self, rendered: Instant) {
+        self.frame_index += 1;
+
+        for mut sample in self.pending_samples.drain(..) {
+            sample.rendered_frame = Some((self.frame_index, rendered));
+            log::debug!(
+                "Latency of {:?}: OS event -> handler {}, handler -> hitsound {}, handler -> frame {} (frame {})",
+                sample.input,
+                format_ms(sample.os_to_handler_ms()),
+                format_ms(sample.handler_to_sound_ms()),
+                format_ms(sample.handler_to_frame_ms()),
+                self.frame_index,
+            );
+            self.samples.push(sample);
+        }
+    }
+
+    pub(crate) fn record_debounced_chatter(&mut self, chatter: DebouncedChatter) {
+        log::debug!(
+            "Discarded chatter of {:?}, released and pressed again {:.2}ms apart",
+            chatter.input,
+            chatter.gap.as_secs_f32() * 1000.0
+        );
+        self.debounced_chatter.push(chatter);
+    }
+
+    /// Logs the latency percentiles of every stage over the presses recorded so far, and the discarded chatter.
+    pub(crate) fn report(&self) {
+        self.report_debounced_chatter();
+
+        if self.samples.is_empty() {
+            log::info!("No presses recorded for latency diagnostics");
+            return;
+        }
+
+        log::info!("Input latency over {} presses:", self.samples.len());
+        let stages: [(&str, fn(&LatencySample) -> Option<f32>); 4] = [
+            ("OS event -> handler", LatencySample::os_to_handler_ms),
+            ("handler -> hitsound", LatencySample::handler_to_sound_ms),
+            ("handler -> frame", LatencySample::handler_to_frame_ms),
+            ("input -> frame", LatencySample::input_to_frame_ms),
+        ];
+        for (name, latency_ms) in stages {
+            let mut latencies_ms = self
+                .samples
+                .iter()
+                .filter_map(latency_ms)
+                .collect::<Vec<_>>();
+            if latencies_ms.is_empty() {
+                log::info!("  {}: no samples", name);
+                continue;
+            }
+            latencies_ms.sort_by(f32::total_cmp);
+
+            let percentiles = REPORTED_PERCENTILES
+                .iter()
+                .map(|p| format!("p{} {:.2}ms", p, percentile(&latencies_ms, *p)))
+                .collect::<Vec<_>>();
+            log::info!(
+                "  {} ({} samples): {}",
+                name,
+                latencies_ms.len(),
+                percentiles.join(", ")
+            );
+        }
+    }
+
+    fn report_debounced_chatter(&self) {
+        let mut chatter_per_input = BTreeMap::<RhythmControlInput, (usize, f32)>::new();
+        for chatter in &self.debounced_chatter {
+            let (count, max_gap_ms) = chatter_per_input.entry(chatter.input).or_default();
+            *count += 1;
+            *max_gap_ms = max_gap_ms.max(chatter.gap.as_secs_f32() * 1000.0);
+        }
+
+        log::info!(
+            "Debouncing discarded {} release and press pairs",
+            self.debounced_chatter.len()
+        );
+        for (input, (count, max_gap_ms)) in chatter_per_input {
+            log::info!(
+                "  {:?}: {} pairs, up to {:.2}ms apart",
+                input,
+                count,
+                max_gap_ms
+            );
+        }
+    }
+
+    /// Drops every sample, e.g. when another chart is loaded.
+    pub(crate) fn clear(&mut self) {
+        self.pending_samples.clear();
+        self.samples.clear();
+        self.debounced_chatter.clear();
+    }
+}
+
+fn elapsed_ms(from: Instant, to: Instant) -> f32 {
+    to.saturating_duration_since(from).as_secs_f32() * 1000.0
+}
+
+fn format_ms(ms: Option<f32>) -> String {
+    ms.map_or_else(|| "-".to_string(), |ms| format!("{:.2}ms", ms))
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted_values: &[f32], p: f32) -> f32 {
+    let rank = (p / 100.0 * sorted_values.len() as f32).ceil() as usize;
+    sorted_values[rank.clamp(1, sorted_values.len()) - 1]
+}
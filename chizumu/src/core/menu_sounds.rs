@@ -0,0 +1,83 @@
+/*!
+ * Sounds of menu navigation, played on the UI track so their volume is set apart from the music and hitsounds.
+ * Each sound is a sample named after it in the menu sounds directory, sounds without a sample stay silent.
+ */
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+
+use super::audio::{AudioSystem, AudioTrack};
+
+pub(crate) const MENU_SOUNDS_DIRECTORY: &str = "assets/sound_effects/menu";
+
+/// Amplitude of the samples relative to the UI track.
+const MENU_SOUND_VOLUME: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum MenuSound {
+    /// The selection moved, e.g. to another chart.
+    Move,
+    Confirm,
+    Back,
+}
+
+impl MenuSound {
+    const ALL: [MenuSound; 3] = [MenuSound::Move, MenuSound::Confirm, MenuSound::Back];
+
+    fn file_name(self) -> &'static str {
+        match self {
+            MenuSound::Move => "move.wav",
+            MenuSound::Confirm => "confirm.wav",
+            MenuSound::Back => "back.wav",
+        }
+    }
+}
+
+/// The menu samples loaded as sound effects on the UI track.
+pub(crate) struct MenuSounds {
+    sound_effects: HashMap<MenuSound, usize>,
+    audio_system: AudioSystem,
+}
+
+impl MenuSounds {
+    /// Samples that fail to load are logged and left out, menus work without sounds.
+    pub(crate) fn load(audio_system: &AudioSystem) -> Self {
+        let mut sound_effects = HashMap::new();
+        for sound in MenuSound::ALL {
+            let file_path = Path::new(MENU_SOUNDS_DIRECTORY).join(sound.file_name());
+            let sound_effect_id = file_path
+                .to_str()
+                .ok_or_else(|| anyhow!("Invalid sample path {:?}", file_path))
+                .and_then(|file_path| {
+                    audio_system.load_sound_effect(file_path, AudioTrack::Ui, MENU_SOUND_VOLUME)
+                });
+            match sound_effect_id {
+                Ok(sound_effect_id) => {
+                    sound_effects.insert(sound, sound_effect_id);
+                }
+                Err(err) => log::warn!("No {:?} menu sound: {}", sound, err),
+            }
+        }
+
+        Self {
+            sound_effects,
+            audio_system: audio_system.clone(),
+        }
+    }
+
+    pub(crate) fn play(&self, sound: MenuSound) -> Result<()> {
+        if let Some(sound_effect_id) = self.sound_effects.get(&sound) {
+            self.audio_system.play_sound_effect(*sound_effect_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MenuSounds {
+    fn drop(&mut self) {
+        for sound_effect_id in self.sound_effects.values() {
+            self.audio_system.unload_sound_effect(*sound_effect_id);
+        }
+    }
+}
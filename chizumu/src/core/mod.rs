@@ -1,2 +1,11 @@
 pub mod audio;
+pub mod gamepad;
+pub mod hitsounds;
 pub mod input;
+pub mod keybindings;
+pub mod latency;
+pub mod menu_sounds;
+pub mod mouse_axis;
+pub mod settings;
+pub mod steering;
+pub mod touch;
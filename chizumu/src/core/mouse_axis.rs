@@ -0,0 +1,51 @@
+/*!
+ * Horizontal mouse motion as a continuous lateral axis, read from raw device events so it is neither bound by the
+ * window's edges nor by the cursor.
+ */
+
+use winit::event::DeviceEvent;
+
+pub(crate) struct MouseAxis {
+    /// In [-1, 1].
+    position: f32,
+    /// Axis distance per unit of raw mouse motion.
+    sensitivity: f32,
+    /// Set when the mouse moved since the axis was last sampled.
+    moved: bool,
+}
+
+impl MouseAxis {
+    pub(crate) fn new(sensitivity: f32) -> Self {
+        Self {
+            position: 0.0,
+            sensitivity,
+            moved: false,
+        }
+    }
+
+    pub(crate) fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    pub(crate) fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (dx, _) } = event {
+            self.position = (self.position + *dx as f32 * self.sensitivity).clamp(-1.0, 1.0);
+            self.moved = true;
+        }
+    }
+
+    pub(crate) fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// The position if the mouse moved since the last call, sampled once per update.
+    pub(crate) fn sample(&mut self) -> Option<f32> {
+        std::mem::take(&mut self.moved).then_some(self.position)
+    }
+
+    /// Centers the axis, e.g. when a chart is loaded.
+    pub(crate) fn reset(&mut self) {
+        self.position = 0.0;
+        self.moved = false;
+    }
+}
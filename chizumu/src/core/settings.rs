@@ -0,0 +1,307 @@
+/*!
+ * Player settings other than the keybindings, kept as a JSON settings file next to the keybindings in the user's
+ * config directory.
+ * The lane layout is an alternative to the keybindings where each key covers a region of cells instead of a note
+ * type, its keys are edited in the settings file.
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use chizumu::chart::NUM_CELLS;
+
+use super::{
+    input::{KeyCode, RhythmControlInput},
+    keybindings::{Binding, Keybindings},
+};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// The settings used to be kept in the keybindings file, they are read from it until they are saved on their own.
+const LEGACY_SETTINGS_FILE_NAME: &str = "keybindings.json";
+
+/// Key chatter of cheap switches stays well below this, while no player can release and press again this fast.
+const DEFAULT_DEBOUNCE_MS: f32 = 8.0;
+
+const DEFAULT_HITSOUND_PACK: &str = "Arcaea";
+
+/// Mouse axis distance per unit of raw mouse motion, the full axis is crossed in about 800 units.
+const DEFAULT_MOUSE_SENSITIVITY: f32 = 0.0025;
+
+/// Releases followed by a press of the same input within the threshold are discarded together as key chatter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DebounceSettings {
+    /// Threshold of inputs without one of their own, 0 turns debouncing off.
+    pub(crate) default_ms: f32,
+    pub(crate) per_input_ms: BTreeMap<RhythmControlInput, f32>,
+}
+
+impl Default for DebounceSettings {
+    fn default() -> Self {
+        Self {
+            default_ms: DEFAULT_DEBOUNCE_MS,
+            per_input_ms: BTreeMap::new(),
+        }
+    }
+}
+
+impl DebounceSettings {
+    pub(crate) fn threshold(&self, input: RhythmControlInput) -> Duration {
+        let threshold_ms = self
+            .per_input_ms
+            .get(&input)
+            .copied()
+            .unwrap_or(self.default_ms);
+        Duration::from_secs_f32(threshold_ms.max(0.0) / 1000.0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for threshold_ms in std::iter::once(&self.default_ms).chain(self.per_input_ms.values()) {
+            if !(threshold_ms.is_finite() && *threshold_ms >= 0.0) {
+                return Err(anyhow!(
+                    "Debounce thresholds must not be negative, got {}ms",
+                    threshold_ms
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keys covering regions of cells, the cells are split as evenly as possible among the keys.
+/// A key hits notes of any type overlapping its region.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LaneLayout {
+    pub(crate) enabled: bool,
+    /// One key per region from left to right, the number of keys is the number of regions.
+    pub(crate) keys: Vec<Binding>,
+}
+
+impl Default for LaneLayout {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys: [KeyCode::D, KeyCode::F, KeyCode::J, KeyCode::K]
+                .into_iter()
+                .map(Binding::Key)
+                .collect(),
+        }
+    }
+}
+
+impl LaneLayout {
+    pub(crate) fn lane_count(&self) -> u32 {
+        self.keys.len() as u32
+    }
+
+    /// Whether the layout is enabled and covers cells with the key, it can not be bound to an input then.
+    pub(crate) fn uses_key(&self, binding: &Binding) -> bool {
+        self.enabled && self.keys.contains(binding)
+    }
+
+    /// Leftmost cell and number of cells of the region.
+    pub(crate) fn region_cells(&self, region_index: u32) -> (u32, u32) {
+        let lane_count = self.lane_count().max(1);
+        let first_cell = region_index * NUM_CELLS / lane_count;
+        let end_cell = (region_index + 1) * NUM_CELLS / lane_count;
+        (first_cell, end_cell - first_cell)
+    }
+
+    /// Cells of each key's region, for looking up physical inputs. Empty while the layout is disabled.
+    pub(crate) fn cell_map(&self) -> HashMap<Binding, (u32, u32)> {
+        if !self.enabled {
+            return HashMap::new();
+        }
+
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(region_index, key)| (*key, self.region_cells(region_index as u32)))
+            .collect()
+    }
+
+    /// One column per cell labeled by the key covering it, regions separated by bars, e.g. `| D D | F F F |`.
+    pub(crate) fn preview(&self) -> String {
+        let labels = self
+            .keys
+            .iter()
+            .map(|key| match key {
+                Binding::Key(keycode) => format!("{:?}", keycode),
+                Binding::Mouse(button) => format!("Mouse{:?}", button),
+            })
+            .collect::<Vec<_>>();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+
+        let regions = labels
+            .iter()
+            .enumerate()
+            .map(|(region_index, label)| {
+                let (_, cell_count) = self.region_cells(region_index as u32);
+                vec![format!("{:^width$}", label, width = label_width); cell_count as usize]
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        format!("| {} |", regions.join(" | "))
+    }
+
+    /// Fails if the layout is enabled and one of its keys is also bound to an input.
+    pub(crate) fn check_keybindings(&self, keybindings: &Keybindings) -> Result<()> {
+        let input_map = keybindings.input_map();
+        match self.keys.iter().find(|key| input_map.contains_key(key)) {
+            Some(key) if self.enabled => Err(anyhow!(
+                "{:?} is bound both to an input and to the lane layout",
+                key
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.keys.is_empty() || self.lane_count() > NUM_CELLS {
+            return Err(anyhow!(
+                "The lane layout needs 1 to {} keys, got {}",
+                NUM_CELLS,
+                self.keys.len()
+            ));
+        }
+
+        let mut keys = self.keys.clone();
+        keys.sort();
+        keys.dedup();
+        if keys.len() != self.keys.len() {
+            return Err(anyhow!("The lane layout uses a key more than once"));
+        }
+        if let Some(key) = keys.iter().find(|key| key.is_reserved()) {
+            return Err(anyhow!("{:?} is reserved and can not be bound", key));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Settings {
+    #[serde(default = "default_mouse_sensitivity")]
+    mouse_sensitivity: f32,
+    #[serde(default)]
+    debounce: DebounceSettings,
+    #[serde(default)]
+    lane_layout: LaneLayout,
+    /// Used for charts that do not pick a pack of their own.
+    #[serde(default = "default_hitsound_pack")]
+    hitsound_pack: String,
+}
+
+fn default_hitsound_pack() -> String {
+    DEFAULT_HITSOUND_PACK.to_string()
+}
+
+fn default_mouse_sensitivity() -> f32 {
+    DEFAULT_MOUSE_SENSITIVITY
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: DEFAULT_MOUSE_SENSITIVITY,
+            debounce: DebounceSettings::default(),
+            lane_layout: LaneLayout::default(),
+            hitsound_pack: default_hitsound_pack(),
+        }
+    }
+}
+
+impl Settings {
+    /// Path of the settings file in the user's config directory.
+    pub(crate) fn default_file_path() -> Result<PathBuf> {
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow!("No user config directory available"))?;
+        Ok(config_dir.join("chizumu").join(SETTINGS_FILE_NAME))
+    }
+
+    /// Default settings if neither the file nor the legacy keybindings file exist yet.
+    pub(crate) fn load(file_path: &Path) -> Result<Self> {
+        let file_path = [
+            file_path.to_path_buf(),
+            file_path.with_file_name(LEGACY_SETTINGS_FILE_NAME),
+        ]
+        .into_iter()
+        .find(|file_path| file_path.exists());
+        let Some(file_path) = file_path else {
+            return Ok(Self::default());
+        };
+
+        // Fields of other files, e.g. the bindings of the legacy file, are ignored.
+        let settings: Self = serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Writes to a temporary file first so a failed write does not lose the existing settings.
+    pub(crate) fn save(&self, file_path: &Path) -> Result<()> {
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temp_file_path = file_path.with_extension("json.tmp");
+        std::fs::write(&temp_file_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temp_file_path, file_path)?;
+
+        Ok(())
+    }
+
+    /// Fails on out of range values, e.g. in a hand edited file.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !(self.mouse_sensitivity.is_finite() && self.mouse_sensitivity > 0.0) {
+            return Err(anyhow!(
+                "Mouse sensitivity must be positive, got {}",
+                self.mouse_sensitivity
+            ));
+        }
+
+        self.debounce.validate()?;
+        self.lane_layout.validate()?;
+
+        Ok(())
+    }
+
+    pub(crate) fn mouse_sensitivity(&self) -> f32 {
+        self.mouse_sensitivity
+    }
+
+    pub(crate) fn debounce(&self) -> &DebounceSettings {
+        &self.debounce
+    }
+
+    pub(crate) fn lane_layout(&self) -> &LaneLayout {
+        &self.lane_layout
+    }
+
+    /// Fails if a key of the lane layout is also bound to an input, the layout is left as it was.
+    pub(crate) fn set_lane_layout_enabled(
+        &mut self,
+        enabled: bool,
+        keybindings: &Keybindings,
+    ) -> Result<()> {
+        let previous_enabled = std::mem::replace(&mut self.lane_layout.enabled, enabled);
+        if let Err(err) = self.lane_layout.check_keybindings(keybindings) {
+            self.lane_layout.enabled = previous_enabled;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn hitsound_pack(&self) -> &str {
+        &self.hitsound_pack
+    }
+
+    pub(crate) fn set_hitsound_pack(&mut self, pack_name: &str) {
+        self.hitsound_pack = pack_name.to_string();
+    }
+}
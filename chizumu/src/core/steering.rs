@@ -0,0 +1,101 @@
+/*!
+ * Lateral steering of the runner from the keyboard, the mouse's x axis or an analog stick.
+ */
+
+use winit::{
+    event::{ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+/// The runner can be steered this far from the center of the playfield on either side.
+pub(crate) const LATERAL_LIMIT: f32 = 2.0;
+
+/// Lateral distance per second covered with a steering key held or a fully tilted stick.
+const STEERING_SPEED: f32 = 3.0;
+
+/// Stick tilts smaller than this are ignored.
+const STICK_DEAD_ZONE: f32 = 0.15;
+
+const STEER_LEFT_KEY: KeyCode = KeyCode::ArrowLeft;
+const STEER_RIGHT_KEY: KeyCode = KeyCode::ArrowRight;
+
+/// Keys and the stick move the runner at a fixed speed, the mouse axis places it directly.
+/// The source used last decides the position.
+pub(crate) struct RunnerSteering {
+    lateral_position: f32,
+    left_held: bool,
+    right_held: bool,
+    /// In [-1, 1].
+    stick_axis: f32,
+}
+
+impl RunnerSteering {
+    pub(crate) fn new() -> Self {
+        Self {
+            lateral_position: 0.0,
+            left_held: false,
+            right_held: false,
+            stick_axis: 0.0,
+        }
+    }
+
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(keycode),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match *keycode {
+                    STEER_LEFT_KEY => self.left_held = pressed,
+                    STEER_RIGHT_KEY => self.right_held = pressed,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `axis` - mouse axis in [-1, 1], mapped to the full steering range.
+    pub(crate) fn set_mouse_axis(&mut self, axis: f32) {
+        self.set_lateral_position(axis * LATERAL_LIMIT);
+    }
+
+    /// `axis` - x axis of an analog stick in [-1, 1].
+    pub(crate) fn set_stick_axis(&mut self, axis: f32) {
+        self.stick_axis = axis.clamp(-1.0, 1.0);
+    }
+
+    /// Places the runner directly, e.g. for autoplay.
+    pub(crate) fn set_lateral_position(&mut self, lateral_position: f32) {
+        self.lateral_position = lateral_position.clamp(-LATERAL_LIMIT, LATERAL_LIMIT);
+    }
+
+    /// Centers the runner and drops held inputs, e.g. when a chart is loaded.
+    pub(crate) fn reset(&mut self) {
+        self.lateral_position = 0.0;
+        self.left_held = false;
+        self.right_held = false;
+        self.stick_axis = 0.0;
+    }
+
+    /// Moves the runner by the held keys and the stick, returns the new lateral position.
+    pub(crate) fn update(&mut self, frame_dt: f32) -> f32 {
+        let stick_axis = if self.stick_axis.abs() < STICK_DEAD_ZONE {
+            0.0
+        } else {
+            self.stick_axis
+        };
+        let direction = (self.right_held as i32 - self.left_held as i32) as f32 + stick_axis;
+
+        self.set_lateral_position(
+            self.lateral_position + direction.clamp(-1.0, 1.0) * STEERING_SPEED * frame_dt,
+        );
+        self.lateral_position
+    }
+}
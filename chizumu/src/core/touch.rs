@@ -0,0 +1,78 @@
+/*!
+ * Mapping of touch points to the playfield, a touch either lands on a lane cell or on the platform beside the lanes.
+ */
+
+use chizumu_rendering::{renderer::ScreenToPlayfield, HIT_AREA_Z_START};
+use nalgebra::Vector2;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use chizumu::chart::NUM_CELLS;
+
+/// Lanes span this far on either side of the playfield's center.
+pub(crate) const LANE_HALF_WIDTH: f32 = 1.0;
+
+/// Touches up to this far past the hit area land on the lanes, touches further away land on the platform.
+const LANE_TOUCH_DEPTH: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TouchTarget {
+    Cell(u32),
+    /// Lateral position on the platform outside of the lanes.
+    Platform(f32),
+}
+
+pub(crate) struct TouchLayout {
+    screen_to_playfield: ScreenToPlayfield,
+    /// In physical pixels.
+    window_size: Vector2<f32>,
+}
+
+impl TouchLayout {
+    pub(crate) fn new(
+        screen_to_playfield: ScreenToPlayfield,
+        window_size: PhysicalSize<u32>,
+    ) -> Self {
+        let mut layout = Self {
+            screen_to_playfield,
+            window_size: Vector2::new(1.0, 1.0),
+        };
+        layout.set_window_size(window_size);
+        layout
+    }
+
+    pub(crate) fn set_window_size(&mut self, window_size: PhysicalSize<u32>) {
+        self.window_size = Vector2::new(
+            window_size.width.max(1) as f32,
+            window_size.height.max(1) as f32,
+        );
+    }
+
+    /// `None` if the touch is above the horizon.
+    pub(crate) fn target(&self, location: PhysicalPosition<f64>) -> Option<TouchTarget> {
+        let screen_position = Vector2::new(
+            location.x as f32 / self.window_size.x,
+            location.y as f32 / self.window_size.y,
+        );
+        let playfield_position = self
+            .screen_to_playfield
+            .playfield_position(screen_position)?;
+
+        match lane_cell(playfield_position.x) {
+            Some(cell) if playfield_position.y <= HIT_AREA_Z_START + LANE_TOUCH_DEPTH => {
+                Some(TouchTarget::Cell(cell))
+            }
+            _ => Some(TouchTarget::Platform(playfield_position.x)),
+        }
+    }
+}
+
+/// Cell of the lanes at the lateral position, `None` beside the lanes.
+pub(crate) fn lane_cell(lateral_position: f32) -> Option<u32> {
+    if lateral_position.abs() >= LANE_HALF_WIDTH {
+        return None;
+    }
+
+    let cell_width = 2.0 * LANE_HALF_WIDTH / NUM_CELLS as f32;
+    let cell = ((lateral_position + LANE_HALF_WIDTH) / cell_width) as u32;
+    Some(cell.min(NUM_CELLS - 1))
+}
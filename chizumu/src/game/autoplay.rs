@@ -0,0 +1,164 @@
+/*!
+ * Input source that plays a chart perfectly, presses land on the notes and holds are held until their end.
+ * Inputs are stamped with the exact song time of their note rather than the frame they are taken in.
+ * The runner is steered along the center of the platform.
+ */
+
+use crate::chart::runtime::{PlatformExtent, RuntimeNote};
+
+use super::{
+    input::RhythmControlInput,
+    judgement::{rhythm_control_input, JudgementInput},
+};
+
+/// Time in seconds a tap is held before being released.
+const TAP_RELEASE_DELAY: f32 = 0.05;
+
+pub struct Autoplay {
+    /// Sorted by time.
+    events: Vec<JudgementInput>,
+    /// Index of the next event to be played.
+    next_event_index: usize,
+    /// Inputs pressed by autoplay and not released yet.
+    held_inputs: Vec<RhythmControlInput>,
+}
+
+impl Autoplay {
+    /// `start_time` - song time autoplay takes over at, events of earlier notes are skipped.
+    pub fn new(notes: &[RuntimeNote], start_time: f32) -> Self {
+        let mut events = Vec::new();
+        for (note_index, note) in notes.iter().enumerate() {
+            let input = rhythm_control_input(note.note_type);
+            events.push(JudgementInput {
+                time: note.offset,
+                input,
+                pressed: true,
+                cells: None,
+                id: None,
+            });
+
+            // Notes of the same input at the same offset are pressed together, each press hits one of them, and
+            // released once after the longest of them so no press is released before the others land.
+            let is_same_press = |other_note: &RuntimeNote| {
+                rhythm_control_input(other_note.note_type) == input
+                    && other_note.offset == note.offset
+            };
+            if notes[..note_index].iter().any(is_same_press) {
+                continue;
+            }
+
+            // Release before the next note of the same input so it can be pressed again.
+            let next_press_time = notes[note_index + 1..]
+                .iter()
+                .find(|next_note| {
+                    rhythm_control_input(next_note.note_type) == input
+                        && next_note.offset > note.offset
+                })
+                .map_or(f32::MAX, |next_note| next_note.offset);
+            let release_time = notes[note_index..]
+                .iter()
+                .filter(|other_note| is_same_press(other_note))
+                .map(|other_note| {
+                    other_note
+                        .end_offset
+                        .unwrap_or(other_note.offset + TAP_RELEASE_DELAY)
+                })
+                .fold(f32::MIN, f32::max)
+                .min(next_press_time);
+
+            events.push(JudgementInput {
+                time: release_time,
+                input,
+                pressed: false,
+                cells: None,
+                id: None,
+            });
+        }
+
+        // Releases go first when they coincide with a press of the same input.
+        events.sort_by(|a, b| a.time.total_cmp(&b.time).then(a.pressed.cmp(&b.pressed)));
+
+        let next_event_index = events.partition_point(|event| event.time < start_time);
+        Self {
+            events,
+            next_event_index,
+            held_inputs: Vec::new(),
+        }
+    }
+
+    /// Takes the events that take place up to and including `time`, each at the song time of its note.
+    pub fn events_until(&mut self, time: f32) -> Vec<JudgementInput> {
+        let events = &self.events[self.next_event_index..];
+        let count = events.iter().take_while(|event| event.time <= time).count();
+        self.next_event_index += count;
+
+        let events = events[..count].to_vec();
+        for event in &events {
+            if event.pressed {
+                self.held_inputs.push(event.input);
+            } else {
+                self.held_inputs.retain(|input| *input != event.input);
+            }
+        }
+        events
+    }
+
+    /// Lateral position keeping the runner on the first of the platforms, `None` where there is no platform.
+    pub fn steering_position(platform_extents: &[PlatformExtent]) -> Option<f32> {
+        platform_extents.first().map(PlatformExtent::center)
+    }
+
+    /// Releases at `time` of every input still held, so nothing stays pressed once autoplay is turned off.
+    pub fn release_all(&mut self, time: f32) -> Vec<JudgementInput> {
+        self.held_inputs
+            .drain(..)
+            .map(|input| JudgementInput {
+                time,
+                input,
+                pressed: false,
+                cells: None,
+                id: None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chart::NoteInputType,
+        game::judgement::{Judgement, JudgementEngine, TimingWindows},
+    };
+
+    use super::*;
+
+    /// Judgements of a whole run of the notes played by autoplay.
+    fn autoplay_judgements(notes: &[RuntimeNote]) -> Vec<Judgement> {
+        let mut engine = JudgementEngine::new(notes, TimingWindows::default());
+        let mut autoplay = Autoplay::new(notes, 0.0);
+        for input in autoplay.events_until(f32::MAX) {
+            engine.update(input.time);
+            engine.process_input(&input);
+        }
+        engine.update(f32::MAX);
+
+        engine
+            .drain_results()
+            .into_iter()
+            .map(|result| result.judgement)
+            .collect()
+    }
+
+    #[test]
+    fn simultaneous_same_input_notes_are_all_hit() {
+        let notes = [
+            RuntimeNote::new(NoteInputType::Tap1, 1.0, None, 0, 2),
+            RuntimeNote::new(NoteInputType::Tap1, 1.0, Some(2.0), 4, 2),
+            RuntimeNote::new(NoteInputType::Tap1, 1.0, None, 8, 2),
+            RuntimeNote::new(NoteInputType::Tap1, 2.5, None, 0, 2),
+        ];
+
+        // The hold is judged twice.
+        assert_eq!(autoplay_judgements(&notes), vec![Judgement::Perfect; 5]);
+    }
+}
@@ -0,0 +1,70 @@
+/*!
+ * Clock sources that drive the conductor's music position.
+ */
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+/// Source of the current music position.
+pub trait MusicClock {
+    /// Current music position in seconds.
+    fn position(&self) -> f32;
+
+    /// Whether the position advances with wall time, positions between reads are only extrapolated for such clocks.
+    fn advances_in_real_time(&self) -> bool {
+        true
+    }
+
+    /// Whether the music played to its end or was stopped, the position does not advance anymore.
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    fn pause(&mut self) -> Result<()>;
+    fn resume(&mut self) -> Result<()>;
+    fn stop(&mut self) -> Result<()>;
+}
+
+/// Clock that only moves when its position is set, for deterministic runs without an audio device.
+/// Clones share the same position, so a clone can be kept to step the clock after it is handed to the conductor.
+#[derive(Clone)]
+pub struct ManualClock {
+    position: Arc<Mutex<f32>>,
+}
+
+impl ManualClock {
+    pub fn new(start_position: f32) -> Self {
+        Self {
+            position: Arc::new(Mutex::new(start_position)),
+        }
+    }
+
+    pub fn set_position(&self, secs: f32) {
+        *self.position.lock() = secs;
+    }
+}
+
+impl MusicClock for ManualClock {
+    fn position(&self) -> f32 {
+        *self.position.lock()
+    }
+
+    fn advances_in_real_time(&self) -> bool {
+        false
+    }
+
+    /// The position is only moved by its owner, pausing has no effect.
+    fn pause(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
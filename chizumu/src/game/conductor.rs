@@ -2,35 +2,170 @@ use std::time::Instant;
 
 use anyhow::Result;
 
-use crate::core::audio::AudioSystem;
+use crate::chart::tempo::{TempoEvent, TempoMap};
 
-use kira::sound::static_sound::StaticSoundHandle;
+use super::clock::MusicClock;
 
-pub(crate) struct Conductor {
-    current_music_handle: Option<StaticSoundHandle>,
-    // XXX: Include manual timer for fine grain music position tracking (Kira has a minimum delta song position).
-    // manual_timer: Instant,
+/// Maximum distance in seconds between the extrapolated and the reported music position before the clock mapping
+/// is snapped to the reported position, e.g. after a stall of the audio thread. Audio backed clocks only advance
+/// once per audio buffer, smaller distances are expected and smoothed out instead.
+const CLOCK_RESYNC_THRESHOLD: f32 = 0.015;
+
+/// Fraction of the distance to the reported music position the clock mapping is moved by on every update, so it
+/// follows the average of the reported positions without jumping with each audio buffer.
+const CLOCK_DRIFT_CORRECTION: f32 = 0.1;
+
+/// A music position and the instant it was read at, positions at other instants are extrapolated from it.
+#[derive(Clone, Copy)]
+struct ClockAnchor {
+    instant: Instant,
+    position: f32,
+}
+
+impl ClockAnchor {
+    fn position_at(&self, instant: Instant) -> f32 {
+        match instant.checked_duration_since(self.instant) {
+            Some(elapsed) => self.position + elapsed.as_secs_f32(),
+            None => self.position - self.instant.duration_since(instant).as_secs_f32(),
+        }
+    }
+}
+
+pub struct Conductor {
+    clock: Option<Box<dyn MusicClock>>,
+    /// Maps instants to music positions finer than the clock reports them, set while a real time clock is playing.
+    clock_anchor: Option<ClockAnchor>,
+    paused: bool,
+    tempo_map: Option<TempoMap>,
+
+    /// Music position of the last update, events are emitted for the range after this position.
+    last_update_position: Option<f32>,
+
+    /// Events crossed during the last update.
+    events: Vec<TempoEvent>,
 }
 
 impl Conductor {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            current_music_handle: None,
+            clock: None,
+            clock_anchor: None,
+            paused: false,
+            tempo_map: None,
+            last_update_position: None,
+            events: Vec::new(),
         }
     }
 
-    pub(crate) fn start_music(
-        &mut self,
-        audio_system: &mut AudioSystem,
-        music_index: usize,
-    ) -> Result<()> {
-        self.current_music_handle = Some(audio_system.play_music(music_index)?);
-        Ok(())
+    /// Drives the conductor with a clock, e.g. the playing music or a manually stepped one for runs without audio.
+    pub fn set_clock(&mut self, clock: Box<dyn MusicClock>) {
+        self.clock = Some(clock);
+        self.clock_anchor = None;
+        self.paused = false;
+        self.last_update_position = None;
+    }
+
+    pub fn get_current_music_position(&self) -> Option<f32> {
+        self.music_position_at(Instant::now())
+    }
+
+    /// Music position at the instant, e.g. to place an input that was received between updates.
+    pub fn music_position_at(&self, instant: Instant) -> Option<f32> {
+        match self.clock_anchor {
+            Some(clock_anchor) => Some(clock_anchor.position_at(instant)),
+            None => self.clock.as_ref().map(|clock| clock.position()),
+        }
     }
 
-    pub(crate) fn get_current_music_position(&self) -> Option<f32> {
-        self.current_music_handle
+    /// Whether the music played to its end or was stopped.
+    pub fn is_music_finished(&self) -> bool {
+        self.clock
             .as_ref()
-            .map(|sound_handle| sound_handle.position() as f32)
+            .map_or(false, |clock| clock.is_finished())
+    }
+
+    /// Moves the clock mapping toward the reported position, it is anchored on the reported position when the
+    /// extrapolation drifted too far away from it.
+    fn sync_clock_anchor(&mut self) {
+        let Some(clock) = self.clock.as_ref() else {
+            return;
+        };
+        if self.paused || !clock.advances_in_real_time() {
+            self.clock_anchor = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let position = clock.position();
+        let position = match self.clock_anchor {
+            Some(clock_anchor) => {
+                let extrapolated_position = clock_anchor.position_at(now);
+                let drift = position - extrapolated_position;
+                if drift.abs() > CLOCK_RESYNC_THRESHOLD {
+                    position
+                } else {
+                    extrapolated_position + drift * CLOCK_DRIFT_CORRECTION
+                }
+            }
+            None => position,
+        };
+        self.clock_anchor = Some(ClockAnchor {
+            instant: now,
+            position,
+        });
+    }
+
+    /// Sets the tempo data used to emit timing events.
+    pub fn set_tempo_map(&mut self, tempo_map: TempoMap) {
+        self.tempo_map = Some(tempo_map);
+        self.last_update_position = None;
+    }
+
+    pub fn pause(&mut self) -> Result<()> {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.pause()?;
+        }
+        self.paused = true;
+        self.clock_anchor = None;
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.resume()?;
+        }
+        self.paused = false;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop()?;
+        }
+        self.paused = true;
+        self.clock_anchor = None;
+        Ok(())
+    }
+
+    /// Collects the beat, measure and tempo change events crossed since the last update and returns them in order.
+    /// All events are emitted when multiple beats pass in a single update, while backwards jumps of the music
+    /// position, e.g. a restarted or looped clock, only resynchronize without emitting anything.
+    pub fn update(&mut self) -> impl Iterator<Item = &TempoEvent> {
+        self.events.clear();
+        self.sync_clock_anchor();
+
+        if let Some(current_position) = self.get_current_music_position() {
+            match self.last_update_position {
+                Some(last_position) if last_position <= current_position => {
+                    if let Some(tempo_map) = &self.tempo_map {
+                        tempo_map.events_between(last_position, current_position, &mut self.events);
+                    }
+                }
+                _ => {}
+            }
+            self.last_update_position = Some(current_position);
+        }
+
+        self.events.iter()
     }
 }
@@ -0,0 +1,71 @@
+/*!
+ * Gameplay events published to the presentation side, e.g. to hide hit notes and play hitsounds.
+ */
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use super::judgement::{Judgement, JudgementResult, NotePart};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent {
+    /// A tap or the start of a hold was hit.
+    NoteHit {
+        /// Index to the chart's notes.
+        note_index: usize,
+        judgement: Judgement,
+        /// Id of the press that hit the note, see `JudgementInput::id`.
+        input_id: Option<u64>,
+    },
+    /// A note passed without being hit.
+    NoteMissed {
+        note_index: usize,
+    },
+    /// A hit hold was released, `Judgement::Miss` if it was released too early.
+    HoldEnded {
+        note_index: usize,
+        judgement: Judgement,
+    },
+    /// The runner was steered off the platform, the gauge drains until it returns.
+    RunnerLeftPlatform,
+    RunnerReturnedToPlatform,
+}
+
+impl GameEvent {
+    /// `None` for results that need no event, i.e. the tail of a hold whose head was missed.
+    pub fn from_result(result: &JudgementResult) -> Option<Self> {
+        match (result.part, result.judgement) {
+            (NotePart::Head, Judgement::Miss) => Some(Self::NoteMissed {
+                note_index: result.note_index,
+            }),
+            (NotePart::Head, judgement) => Some(Self::NoteHit {
+                note_index: result.note_index,
+                judgement,
+                input_id: result.input_id,
+            }),
+            (NotePart::Tail, _) if result.offset_ms.is_none() => None,
+            (NotePart::Tail, judgement) => Some(Self::HoldEnded {
+                note_index: result.note_index,
+                judgement,
+            }),
+        }
+    }
+}
+
+/// Sends every published event to all subscribers.
+#[derive(Default)]
+pub struct GameEventChannel {
+    senders: Vec<Sender<GameEvent>>,
+}
+
+impl GameEventChannel {
+    pub fn subscribe(&mut self) -> Receiver<GameEvent> {
+        let (sender, receiver) = channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Subscribers whose receiver was dropped are removed.
+    pub fn publish(&mut self, event: GameEvent) {
+        self.senders.retain(|sender| sender.send(event).is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+/*!
+ * Life gauge fed by judgement results, decides whether a run clears or fails.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use super::judgement::Judgement;
+
+const GAUGE_MAX: f32 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GaugeType {
+    /// Starts low and fills up, can only fail at the end of the chart.
+    Normal,
+    /// Starts full and never recovers, fails as soon as it empties.
+    Hard,
+    /// Starts full and recovers, fails as soon as it empties.
+    Survival,
+}
+
+/// Gauge change in percent for each judgement.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GaugeChanges {
+    pub perfect: f32,
+    pub great: f32,
+    pub good: f32,
+    pub miss: f32,
+    /// Change per second the runner spends off the platform.
+    pub off_platform: f32,
+}
+
+impl GaugeChanges {
+    fn get(&self, judgement: Judgement) -> f32 {
+        match judgement {
+            Judgement::Perfect => self.perfect,
+            Judgement::Great => self.great,
+            Judgement::Good => self.good,
+            Judgement::Miss => self.miss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GaugeSettings {
+    pub gauge_type: GaugeType,
+    pub changes: GaugeChanges,
+    pub initial_value: f32,
+    /// Minimum gauge value in percent at the end of the chart for the run to clear.
+    pub clear_threshold: f32,
+    /// Ends the run as soon as the gauge fails instead of playing until the end of the chart.
+    pub fail_stop: bool,
+}
+
+impl GaugeSettings {
+    /// Default settings of each gauge type.
+    pub fn new(gauge_type: GaugeType) -> Self {
+        match gauge_type {
+            GaugeType::Normal => Self {
+                gauge_type,
+                changes: GaugeChanges {
+                    perfect: 1.0,
+                    great: 0.8,
+                    good: 0.4,
+                    miss: -4.0,
+                    off_platform: -8.0,
+                },
+                initial_value: 20.0,
+                clear_threshold: 70.0,
+                fail_stop: false,
+            },
+            GaugeType::Hard => Self {
+                gauge_type,
+                changes: GaugeChanges {
+                    perfect: 0.0,
+                    great: 0.0,
+                    good: -1.0,
+                    miss: -10.0,
+                    off_platform: -20.0,
+                },
+                initial_value: GAUGE_MAX,
+                clear_threshold: 0.0,
+                fail_stop: true,
+            },
+            GaugeType::Survival => Self {
+                gauge_type,
+                changes: GaugeChanges {
+                    perfect: 0.5,
+                    great: 0.3,
+                    good: 0.0,
+                    miss: -6.0,
+                    off_platform: -12.0,
+                },
+                initial_value: GAUGE_MAX,
+                clear_threshold: 0.0,
+                fail_stop: true,
+            },
+        }
+    }
+}
+
+impl Default for GaugeSettings {
+    fn default() -> Self {
+        Self::new(GaugeType::Normal)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClearStatus {
+    InProgress,
+    Cleared,
+    Failed,
+}
+
+pub struct Gauge {
+    settings: GaugeSettings,
+    /// In percent.
+    value: f32,
+    failed: bool,
+}
+
+impl Gauge {
+    pub fn new(settings: GaugeSettings) -> Self {
+        Self {
+            settings,
+            value: settings.initial_value.clamp(0.0, GAUGE_MAX),
+            failed: false,
+        }
+    }
+
+    pub fn settings(&self) -> &GaugeSettings {
+        &self.settings
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Whether the gauge emptied during the run, only possible for gauge types that fail early.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    pub fn apply_judgement(&mut self, judgement: Judgement) {
+        self.apply_change(self.settings.changes.get(judgement));
+    }
+
+    /// `secs` - time the runner spent off the platform since the last call.
+    pub fn apply_off_platform(&mut self, secs: f32) {
+        self.apply_change(self.settings.changes.off_platform * secs);
+    }
+
+    /// Applies an arbitrary change in percent, e.g. damage outside of judgements.
+    pub fn apply_change(&mut self, change: f32) {
+        if self.failed {
+            return;
+        }
+
+        self.value = (self.value + change).clamp(0.0, GAUGE_MAX);
+        if self.value <= 0.0 && self.settings.gauge_type != GaugeType::Normal {
+            self.failed = true;
+        }
+    }
+
+    /// `run_ended` - whether the music or the chart is over.
+    pub fn clear_status(&self, run_ended: bool) -> ClearStatus {
+        if self.failed {
+            ClearStatus::Failed
+        } else if !run_ended {
+            ClearStatus::InProgress
+        } else if self.value >= self.settings.clear_threshold {
+            ClearStatus::Cleared
+        } else {
+            ClearStatus::Failed
+        }
+    }
+}
@@ -0,0 +1,40 @@
+/*!
+ * Rhythm control inputs the game is played with, independent of the devices they come from.
+ */
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RhythmControlInput {
+    Tap1,
+    Tap2,
+    Tap3,
+    Tap4,
+    TapMove1,
+    TapMove2,
+    TapWide,
+    /// A touch on the lanes, hits notes of any type in the touched cells. Not bindable.
+    Touch,
+    /// A key of the lane layout, hits notes of any type in the cells of its region. Not bindable.
+    Lane,
+}
+
+impl RhythmControlInput {
+    /// Inputs that can be bound to keys and buttons.
+    pub const ALL: [RhythmControlInput; 7] = [
+        RhythmControlInput::Tap1,
+        RhythmControlInput::Tap2,
+        RhythmControlInput::Tap3,
+        RhythmControlInput::Tap4,
+        RhythmControlInput::TapMove1,
+        RhythmControlInput::TapMove2,
+        RhythmControlInput::TapWide,
+    ];
+}
+
+/// A press or release of a rhythm control input.
+#[derive(Debug, Clone, Copy)]
+pub struct RhythmControlEvent {
+    pub input: RhythmControlInput,
+    pub pressed: bool,
+}
@@ -0,0 +1,334 @@
+/*!
+ * Matching of rhythm control inputs to chart notes and grading of their timing.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::chart::{runtime::RuntimeNote, NoteInputType};
+
+use super::input::RhythmControlInput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+/// Maximum absolute hit offsets in milliseconds for each judgement.
+/// Presses further away from a note than the good window are not matched to it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TimingWindows {
+    pub perfect: f32,
+    pub great: f32,
+    pub good: f32,
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        Self {
+            perfect: 33.0,
+            great: 66.0,
+            good: 100.0,
+        }
+    }
+}
+
+impl TimingWindows {
+    /// Returns `None` if the offset falls outside of all windows.
+    pub fn judge(&self, offset_ms: f32) -> Option<Judgement> {
+        let offset_ms = offset_ms.abs();
+        if offset_ms <= self.perfect {
+            Some(Judgement::Perfect)
+        } else if offset_ms <= self.great {
+            Some(Judgement::Great)
+        } else if offset_ms <= self.good {
+            Some(Judgement::Good)
+        } else {
+            None
+        }
+    }
+}
+
+/// A press or release at a given song time.
+#[derive(Debug, Clone, Copy)]
+pub struct JudgementInput {
+    /// Song time in seconds.
+    pub time: f32,
+    pub input: RhythmControlInput,
+    pub pressed: bool,
+    /// Leftmost cell and number of cells covered by the input, `None` if the input is not tied to cells.
+    pub cells: Option<(u32, u32)>,
+    /// Identifies a live input so its judgement can be traced back to it, `None` if it is not followed.
+    pub id: Option<u64>,
+}
+
+/// The part of a note that was judged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotePart {
+    /// The tap or the start of a hold.
+    Head,
+    /// The release at the end of a hold.
+    Tail,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct JudgementResult {
+    /// Index to the chart's notes.
+    pub note_index: usize,
+    pub part: NotePart,
+    pub judgement: Judgement,
+    /// Input time minus the note's time in milliseconds, `None` for misses without any input.
+    pub offset_ms: Option<f32>,
+    /// Song time in seconds at which the judgement was made.
+    pub time: f32,
+    /// Id of the input that was judged, `None` if there was no input or it is not followed.
+    pub input_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NoteProgress {
+    Pending,
+    /// Head was hit, waiting for the hold to end.
+    Holding(RhythmControlInput),
+    Done,
+}
+
+/// Type of the notes hit by the input, `None` if it hits notes of any type.
+pub fn note_input_type(input: RhythmControlInput) -> Option<NoteInputType> {
+    let note_type = match input {
+        RhythmControlInput::Tap1 => NoteInputType::Tap1,
+        RhythmControlInput::Tap2 => NoteInputType::Tap2,
+        RhythmControlInput::Tap3 => NoteInputType::Tap3,
+        RhythmControlInput::Tap4 => NoteInputType::Tap4,
+        RhythmControlInput::TapMove1 => NoteInputType::TapMove1,
+        RhythmControlInput::TapMove2 => NoteInputType::TapMove2,
+        RhythmControlInput::TapWide => NoteInputType::TapWidth,
+        RhythmControlInput::Touch | RhythmControlInput::Lane => return None,
+    };
+    Some(note_type)
+}
+
+/// Input that hits notes of the given type.
+pub fn rhythm_control_input(note_type: NoteInputType) -> RhythmControlInput {
+    match note_type {
+        NoteInputType::Tap1 => RhythmControlInput::Tap1,
+        NoteInputType::Tap2 => RhythmControlInput::Tap2,
+        NoteInputType::Tap3 => RhythmControlInput::Tap3,
+        NoteInputType::Tap4 => RhythmControlInput::Tap4,
+        NoteInputType::TapMove1 => RhythmControlInput::TapMove1,
+        NoteInputType::TapMove2 => RhythmControlInput::TapMove2,
+        NoteInputType::TapWidth => RhythmControlInput::TapWide,
+    }
+}
+
+/// Judges inputs against a chart's notes.
+/// Inputs up to a song time should be processed before `update` is called with that time, results are then
+/// independent of how the song time is split into updates.
+pub struct JudgementEngine {
+    /// Sorted by offset.
+    notes: Vec<RuntimeNote>,
+    progress: Vec<NoteProgress>,
+    windows: TimingWindows,
+
+    /// Index of the first note that is not fully judged yet.
+    first_pending_index: usize,
+    results: Vec<JudgementResult>,
+}
+
+impl JudgementEngine {
+    pub fn new(notes: &[RuntimeNote], windows: TimingWindows) -> Self {
+        Self {
+            notes: notes.to_vec(),
+            progress: vec![NoteProgress::Pending; notes.len()],
+            windows,
+            first_pending_index: 0,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn windows(&self) -> &TimingWindows {
+        &self.windows
+    }
+
+    /// Number of judgements produced over the whole chart, holds are judged twice.
+    pub fn total_judgement_count(&self) -> usize {
+        self.notes
+            .iter()
+            .map(|note| if note.is_hold() { 2 } else { 1 })
+            .sum()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.first_pending_index >= self.notes.len()
+    }
+
+    pub fn process_input(&mut self, input: &JudgementInput) {
+        if input.pressed {
+            self.process_press(input);
+        } else {
+            self.process_release(input);
+        }
+    }
+
+    fn process_press(&mut self, input: &JudgementInput) {
+        let note_type = note_input_type(input.input);
+        let good_window_secs = self.windows.good / 1000.0;
+
+        // Notes are sorted, the earliest matching note inside the window is hit.
+        let candidate = (self.first_pending_index..self.notes.len())
+            .take_while(|&i| self.notes[i].offset - input.time <= good_window_secs)
+            .find(|&i| {
+                let note = &self.notes[i];
+                self.progress[i] == NoteProgress::Pending
+                    && note_type.map_or(true, |note_type| note.note_type == note_type)
+                    && input.cells.map_or(true, |cells| cells_overlap(note, cells))
+                    && (input.time - note.offset).abs() <= good_window_secs
+            });
+
+        if let Some(note_index) = candidate {
+            let offset_ms = (input.time - self.notes[note_index].offset) * 1000.0;
+            self.push_result(
+                note_index,
+                NotePart::Head,
+                self.windows.judge(offset_ms).unwrap_or(Judgement::Good),
+                Some(offset_ms),
+                input.time,
+                input.id,
+            );
+
+            self.progress[note_index] = if self.notes[note_index].is_hold() {
+                NoteProgress::Holding(input.input)
+            } else {
+                NoteProgress::Done
+            };
+        }
+    }
+
+    fn process_release(&mut self, input: &JudgementInput) {
+        for note_index in self.first_pending_index..self.notes.len() {
+            if self.notes[note_index].offset > input.time {
+                break;
+            }
+            if self.progress[note_index] != NoteProgress::Holding(input.input)
+                || !input
+                    .cells
+                    .map_or(true, |cells| cells_overlap(&self.notes[note_index], cells))
+            {
+                continue;
+            }
+
+            let end_offset = self.notes[note_index].end_offset.unwrap();
+            if input.time >= end_offset {
+                // Held until the end, judged the same as if the hold was completed by `update`.
+                self.push_result(
+                    note_index,
+                    NotePart::Tail,
+                    Judgement::Perfect,
+                    Some(0.0),
+                    end_offset,
+                    input.id,
+                );
+            } else {
+                let offset_ms = (input.time - end_offset) * 1000.0;
+                let judgement = self.windows.judge(offset_ms).unwrap_or(Judgement::Miss);
+                self.push_result(
+                    note_index,
+                    NotePart::Tail,
+                    judgement,
+                    Some(offset_ms),
+                    input.time,
+                    input.id,
+                );
+            }
+            self.progress[note_index] = NoteProgress::Done;
+        }
+    }
+
+    /// Judges notes that passed their window untouched and holds that were held until their end.
+    pub fn update(&mut self, time: f32) {
+        let good_window_secs = self.windows.good / 1000.0;
+
+        for note_index in self.first_pending_index..self.notes.len() {
+            let note = &self.notes[note_index];
+            if note.offset > time {
+                break;
+            }
+
+            match self.progress[note_index] {
+                NoteProgress::Pending if time - note.offset > good_window_secs => {
+                    // Judged at the end of the window so results do not depend on when updates happen.
+                    let miss_time = note.offset + good_window_secs;
+                    let is_hold = note.is_hold();
+                    self.push_result(
+                        note_index,
+                        NotePart::Head,
+                        Judgement::Miss,
+                        None,
+                        miss_time,
+                        None,
+                    );
+                    if is_hold {
+                        self.push_result(
+                            note_index,
+                            NotePart::Tail,
+                            Judgement::Miss,
+                            None,
+                            miss_time,
+                            None,
+                        );
+                    }
+                    self.progress[note_index] = NoteProgress::Done;
+                }
+                NoteProgress::Holding(_) if time >= note.end_offset.unwrap() => {
+                    let end_offset = note.end_offset.unwrap();
+                    self.push_result(
+                        note_index,
+                        NotePart::Tail,
+                        Judgement::Perfect,
+                        Some(0.0),
+                        end_offset,
+                        None,
+                    );
+                    self.progress[note_index] = NoteProgress::Done;
+                }
+                _ => {}
+            }
+        }
+
+        while self.first_pending_index < self.notes.len()
+            && self.progress[self.first_pending_index] == NoteProgress::Done
+        {
+            self.first_pending_index += 1;
+        }
+    }
+
+    /// Takes all results produced since the last call, in order of judgement.
+    pub fn drain_results(&mut self) -> Vec<JudgementResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    fn push_result(
+        &mut self,
+        note_index: usize,
+        part: NotePart,
+        judgement: Judgement,
+        offset_ms: Option<f32>,
+        time: f32,
+        input_id: Option<u64>,
+    ) {
+        self.results.push(JudgementResult {
+            note_index,
+            part,
+            judgement,
+            offset_ms,
+            time,
+            input_id,
+        });
+    }
+}
+
+fn cells_overlap(note: &RuntimeNote, (cell, width): (u32, u32)) -> bool {
+    cell < note.cell + note.width && note.cell < cell + width
+}
@@ -2,59 +2,596 @@
  * Game logic.
  */
 
-use crate::{
-    chart::{runtime::*, *},
-    core::audio::AudioSystem,
-};
+use std::sync::mpsc::Receiver;
 
-use parking_lot::Mutex;
+use anyhow::{anyhow, Result};
 
+use crate::chart::runtime::*;
+
+use conductor::Conductor;
+use events::{GameEvent, GameEventChannel};
+use gauge::{ClearStatus, Gauge, GaugeSettings};
+use judgement::{JudgementEngine, JudgementInput, JudgementResult, TimingWindows};
+use replay::{Replay, ReplayPlayer, ReplayRecorder, ReplaySettings, SteeringEvent};
+use results::{ResultsRecorder, RunResults};
+use score::{scoring_rule_from_name, MillionScoring, ScoreTracker, ScoringRule};
+
+pub mod autoplay;
+pub mod clock;
 pub mod conductor;
+pub mod events;
+pub mod gauge;
+pub mod input;
+pub mod judgement;
+pub mod replay;
+pub mod results;
+pub mod score;
+pub mod score_db;
+
+/// The time off the platform is accounted in stretches ending at least on every multiple of this, in seconds.
+/// A power of two so the multiples are exact in floating point.
+const RUNNER_CHECK_INTERVAL: f32 = 1.0 / 256.0;
+
+/// Bisection steps locating a platform edge crossing within a stretch, about 0.1ms apart at the end.
+const EDGE_CROSSING_ITERATIONS: u32 = 5;
+
+/// The player's own settings, put aside while a replay is played back with the settings it was recorded with.
+struct PlayerSettings {
+    timing_windows: TimingWindows,
+    gauge_settings: GaugeSettings,
+    scoring_rule: Box<dyn ScoringRule>,
+}
 
 pub struct GameState {
-    /// For testing purposes.
-    audio_system: Mutex<AudioSystem>,
+    /// Keeps track of the music position, driven by either the music or a manual clock.
+    conductor: Conductor,
 
     /// Current song information.
-    ///
-    /// Current song position in seconds.
-    // current_song_position: f32,
     chart: Option<RuntimeChart>,
-    current_note_index: usize,
+
+    timing_windows: TimingWindows,
+    /// Created for the current chart.
+    judgement_engine: Option<JudgementEngine>,
+    score_tracker: ScoreTracker,
+    results_recorder: ResultsRecorder,
+    gauge_settings: GaugeSettings,
+    gauge: Gauge,
+    replay_recorder: ReplayRecorder,
+    /// Set while a replay is played back, live inputs are ignored meanwhile.
+    replay_player: Option<ReplayPlayer>,
+    /// Set while the replay's settings are in use, restored when the next chart is set.
+    player_settings: Option<PlayerSettings>,
+    event_channel: GameEventChannel,
+
+    /// Speed map of the current chart to look up platform extents with.
+    /// Extents at a song time do not depend on the runner speed, a unit speed is used.
+    platform_speed_map: Option<RunnerSpeedMap>,
+    /// X axis position of the runner, in the same space as platform placements.
+    runner_lateral_position: f32,
+    /// Steering taken since the last update, applied in order of song time with the update's judgements.
+    pending_steering: Vec<SteeringEvent>,
+    /// Whether the runner was on the platform at `runner_checked_until`.
+    runner_on_platform: bool,
+    /// Song time up to which the time spent off the platform is accounted.
+    runner_checked_until: f32,
+
+    /// Set when the chart is over or the run is stopped early by a failed gauge.
+    run_ended: bool,
 }
 
 impl GameState {
     pub fn new() -> Self {
         Self {
+            conductor: Conductor::new(),
             chart: None,
-            audio_system: Mutex::new(AudioSystem::new().unwrap()),
-            current_note_index: 0,
+            timing_windows: TimingWindows::default(),
+            judgement_engine: None,
+            score_tracker: ScoreTracker::new(Box::new(MillionScoring)),
+            results_recorder: ResultsRecorder::default(),
+            gauge_settings: GaugeSettings::default(),
+            gauge: Gauge::new(GaugeSettings::default()),
+            replay_recorder: ReplayRecorder::default(),
+            replay_player: None,
+            player_settings: None,
+            event_channel: GameEventChannel::default(),
+            platform_speed_map: None,
+            runner_lateral_position: 0.0,
+            pending_steering: Vec::new(),
+            runner_on_platform: true,
+            runner_checked_until: 0.0,
+            run_ended: false,
+        }
+    }
+
+    pub fn chart(&self) -> Option<&RuntimeChart> {
+        self.chart.as_ref()
+    }
+
+    /// Receives hit, miss and hold events as notes are judged.
+    pub fn subscribe_events(&mut self) -> Receiver<GameEvent> {
+        self.event_channel.subscribe()
+    }
+
+    pub fn conductor(&self) -> &Conductor {
+        &self.conductor
+    }
+
+    pub fn conductor_mut(&mut self) -> &mut Conductor {
+        &mut self.conductor
+    }
+
+    /// Takes effect on the next chart that is set.
+    pub fn set_timing_windows(&mut self, timing_windows: TimingWindows) {
+        self.timing_windows = timing_windows;
+    }
+
+    pub fn set_scoring_rule(&mut self, rule: Box<dyn ScoringRule>) {
+        self.score_tracker.set_rule(rule);
+    }
+
+    /// Score, combo and accuracy of the current run.
+    pub fn score_tracker(&self) -> &ScoreTracker {
+        &self.score_tracker
+    }
+
+    /// Takes effect on the next chart that is set.
+    pub fn set_gauge_settings(&mut self, gauge_settings: GaugeSettings) {
+        self.gauge_settings = gauge_settings;
+    }
+
+    pub fn gauge(&self) -> &Gauge {
+        &self.gauge
+    }
+
+    pub fn runner_lateral_position(&self) -> f32 {
+        self.runner_lateral_position
+    }
+
+    pub fn is_runner_on_platform(&self) -> bool {
+        self.runner_on_platform
+    }
+
+    /// Extents of the platforms placed at `secs` seconds, empty without a chart.
+    pub fn platform_extents(&self, secs: f32) -> Vec<PlatformExtent> {
+        match (&self.chart, &self.platform_speed_map) {
+            (Some(chart), Some(speed_map)) => chart.platform_extents(speed_map, secs),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the run is over, either by the music or the chart ending or by a fail-stop.
+    pub fn is_run_ended(&self) -> bool {
+        self.run_ended || self.conductor.is_music_finished()
+    }
+
+    /// Whether every note is judged, the score does not change anymore.
+    fn is_score_final(&self) -> bool {
+        self.judgement_engine
+            .as_ref()
+            .map_or(false, |judgement_engine| judgement_engine.is_finished())
+    }
+
+    /// The chart is over once its last note and platform passed and the score is final.
+    fn is_chart_over(&self, secs: f32) -> bool {
+        self.chart
+            .as_ref()
+            .map_or(false, |chart| secs >= chart.end_offset())
+            && self.is_score_final()
+    }
+
+    pub fn clear_status(&self) -> ClearStatus {
+        self.gauge.clear_status(self.is_run_ended())
+    }
+
+    /// Detailed results of the current run so far, `None` without a chart.
+    pub fn results(&self) -> Option<RunResults> {
+        self.chart.as_ref().map(|chart| {
+            self.results_recorder.build(
+                chart,
+                &self.score_tracker,
+                &self.gauge,
+                self.clear_status(),
+            )
+        })
+    }
+
+    /// Settings the current run is judged with.
+    fn replay_settings(&self, chart: &RuntimeChart) -> ReplaySettings {
+        ReplaySettings {
+            timing_windows: self
+                .judgement_engine
+                .as_ref()
+                .map_or(self.timing_windows, |judgement_engine| {
+                    *judgement_engine.windows()
+                }),
+            gauge_settings: *self.gauge.settings(),
+            scoring_rule: String::from(self.score_tracker.rule_name()),
+            modifiers: *chart.modifiers(),
+        }
+    }
+
+    /// Replay of the inputs of the current run so far, `None` without a chart.
+    pub fn replay(&self) -> Option<Replay> {
+        self.chart.as_ref().map(|chart| {
+            self.replay_recorder
+                .create_replay(chart.chart_info.chart_hash, self.replay_settings(chart))
+        })
+    }
+
+    /// Restarts the run of the current chart with the replay's settings and plays back its inputs.
+    /// The chart has to be built with the replay's modifiers, should be called before the music is started.
+    /// The player's settings are restored when the next chart is set.
+    pub fn start_replay(&mut self, replay: Replay) -> Result<()> {
+        let chart = self
+            .chart
+            .as_ref()
+            .ok_or_else(|| anyhow!("No chart is set to play the replay on"))?;
+        if replay.chart_hash != chart.chart_info.chart_hash {
+            return Err(anyhow!(
+                "Replay was recorded on a different chart (hash {:x}, current chart {:x})",
+                replay.chart_hash,
+                chart.chart_info.chart_hash
+            ));
+        }
+        if replay.settings.modifiers != *chart.modifiers() {
+            return Err(anyhow!(
+                "Replay was recorded with modifiers {:?}, the chart is built with {:?}",
+                replay.settings.modifiers,
+                chart.modifiers()
+            ));
+        }
+
+        let previous_rule = self
+            .score_tracker
+            .set_rule(scoring_rule_from_name(&replay.settings.scoring_rule)?);
+        if self.player_settings.is_none() {
+            self.player_settings = Some(PlayerSettings {
+                timing_windows: self.timing_windows,
+                gauge_settings: self.gauge_settings,
+                scoring_rule: previous_rule,
+            });
+        }
+        self.timing_windows = replay.settings.timing_windows;
+        self.gauge_settings = replay.settings.gauge_settings;
+        self.reset_run();
+        self.replay_player = Some(ReplayPlayer::new(replay));
+
+        Ok(())
+    }
+
+    pub fn is_playing_replay(&self) -> bool {
+        self.replay_player.is_some()
+    }
+
+    /// Inputs should be handled in order and before the update for the music position they took place at.
+    /// Ignored while a replay is played back.
+    pub fn handle_input(&mut self, input: JudgementInput) {
+        if self.replay_player.is_none() {
+            self.process_input(input);
+        }
+    }
+
+    fn process_input(&mut self, input: JudgementInput) {
+        if self.run_ended {
+            return;
+        }
+        if let Some(judgement_engine) = self.judgement_engine.as_mut() {
+            judgement_engine.process_input(&input);
+            self.replay_recorder.record(&input);
+        }
+    }
+
+    /// Steering should be handled in order and before the update for the music position it took place at.
+    /// Ignored while a replay is played back.
+    pub fn steer_runner(&mut self, event: SteeringEvent) {
+        if self.replay_player.is_none() {
+            self.process_steering(event);
+        }
+    }
+
+    /// Only changes of the lateral position are recorded, they take effect with the next update.
+    fn process_steering(&mut self, event: SteeringEvent) {
+        if self.run_ended || self.chart.is_none() {
+            return;
+        }
+        let lateral_position = self
+            .pending_steering
+            .last()
+            .map_or(self.runner_lateral_position, |pending| {
+                pending.lateral_position
+            });
+        if event.lateral_position != lateral_position {
+            self.pending_steering.push(event);
+            self.replay_recorder.record_steering(event);
+        }
+    }
+
+    /// Advances the game to the conductor's current music position.
+    pub fn update(&mut self) {
+        self.update_until(f32::INFINITY);
+    }
+
+    /// Advances the game to the conductor's current music position but no further than `max_secs`, e.g. while
+    /// inputs that took place before the current position are still to be handled.
+    pub fn update_until(&mut self, max_secs: f32) {
+        for event in self.conductor.update() {
+            log::trace!("Conductor event {:?}", event);
+        }
+
+        if let Some(secs) = self.conductor.get_current_music_position() {
+            self.update_current_music_position(secs.min(max_secs));
         }
     }
 
     pub fn update_current_music_position(&mut self, secs: f32) {
-        // if let Some(chart) = &self.chart {
-        //     while self.current_note_index < chart.notes.len() {
-        //         if chart.notes[self.current_note_index].offset < secs {
-        //             // log::debug!(
-        //             //     "Note offset {} less than song position {}",
-        //             //     self.chart.notes[self.current_note_index].offset,
-        //             //     secs
-        //             // );
-        //             self.audio_system
-        //                 .lock()
-        //                 .play_sound_effect(SFX_TAP_A_INDEX)
-        //                 .unwrap();
-
-        //             self.current_note_index += 1;
-        //         } else {
-        //             break;
-        //         }
-        //     }
-        // }
+        if self.run_ended {
+            return;
+        }
+
+        if let Some(replay_player) = self.replay_player.as_mut() {
+            let inputs = replay_player.events_until(secs);
+            let steering = replay_player.steering_until(secs);
+            for input in inputs {
+                self.process_input(input);
+            }
+            for event in steering {
+                self.process_steering(event);
+            }
+        }
+
+        let mut results = match self.judgement_engine.as_mut() {
+            Some(judgement_engine) => {
+                judgement_engine.update(secs);
+                judgement_engine.drain_results()
+            }
+            None => Vec::new(),
+        };
+        // Judgements of inputs come before the misses found by the update, they are put in order of song time.
+        results.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        // Judgements and steering are applied in order of song time, with the time off the platform accounted up
+        // to each of them. The gauge then does not depend on how the song time is split into updates.
+        let mut steering = std::mem::take(&mut self.pending_steering)
+            .into_iter()
+            .peekable();
+        for result in results {
+            while let Some(event) = steering.next_if(|event| event.time < result.time) {
+                self.account_runner_until(event.time);
+                self.runner_lateral_position = event.lateral_position;
+            }
+            self.account_runner_until(result.time);
+            self.apply_result(&result);
+            if self.stop_if_gauge_failed(result.time) {
+                return;
+            }
+        }
+        for event in steering {
+            self.account_runner_until(event.time);
+            self.runner_lateral_position = event.lateral_position;
+        }
+        // The stretch after the last check time is accounted once it is complete.
+        self.account_runner_until(last_runner_check_time(secs));
+        if self.stop_if_gauge_failed(secs) {
+            return;
+        }
+
+        if self.is_chart_over(secs) {
+            log::info!("Chart is over at {}s, ending the run.", secs);
+            self.run_ended = true;
+        }
+    }
+
+    fn apply_result(&mut self, result: &JudgementResult) {
+        log::debug!("Judgement {:?}", result);
+        self.score_tracker.add_result(result);
+        self.results_recorder.record(result);
+        self.gauge.apply_judgement(result.judgement);
+        if let Some(event) = GameEvent::from_result(result) {
+            self.event_channel.publish(event);
+        }
+    }
+
+    /// Ends the run if the gauge is failed and set to stop the run, returns whether it did.
+    fn stop_if_gauge_failed(&mut self, secs: f32) -> bool {
+        if !(self.gauge.is_failed() && self.gauge.settings().fail_stop) {
+            return false;
+        }
+
+        log::info!("Gauge emptied at {}s, stopping the run.", secs);
+        self.run_ended = true;
+        if let Err(err) = self.conductor.stop() {
+            log::error!("Failed to stop the conductor: {}", err);
+        }
+        true
+    }
+
+    /// Drains the gauge for the time off the platform up to `until`, at the current lateral position.
+    /// The time is split into stretches ending on the check times in between and at `until`. `until` is the song
+    /// time of a judgement, a steering event or a check time, so the stretches are the same however the song time
+    /// is split into updates. Stretches without any platform do not count as off the platform.
+    fn account_runner_until(&mut self, until: f32) {
+        while self.runner_checked_until < until {
+            let from = self.runner_checked_until;
+            let to = next_runner_check_time(from).min(until);
+
+            let on_platform_from = self.is_runner_on_platform_at(from);
+            let on_platform_to = self.is_runner_on_platform_at(to);
+            let off_platform_secs = match (on_platform_from, on_platform_to) {
+                (true, true) => 0.0,
+                (false, false) => to - from,
+                (true, false) => to - self.platform_edge_crossing(from, to),
+                (false, true) => self.platform_edge_crossing(from, to) - from,
+            };
+            if off_platform_secs > 0.0 {
+                self.gauge.apply_off_platform(off_platform_secs);
+                self.results_recorder.record_off_platform(off_platform_secs);
+            }
+
+            // Steering may have moved the runner on or off the platform at the start of the stretch.
+            self.set_runner_on_platform(on_platform_from);
+            self.set_runner_on_platform(on_platform_to);
+            self.runner_checked_until = to;
+        }
+    }
+
+    fn is_runner_on_platform_at(&self, secs: f32) -> bool {
+        let extents = self.platform_extents(secs);
+        extents.is_empty()
+            || extents
+                .iter()
+                .any(|extent| extent.contains(self.runner_lateral_position))
+    }
+
+    /// Song time the runner crosses a platform edge at, between two times it is on the platform at only one of.
+    fn platform_edge_crossing(&self, mut from: f32, mut to: f32) -> f32 {
+        let on_platform_from = self.is_runner_on_platform_at(from);
+        for _ in 0..EDGE_CROSSING_ITERATIONS {
+            let middle = (from + to) / 2.0;
+            if self.is_runner_on_platform_at(middle) == on_platform_from {
+                from = middle;
+            } else {
+                to = middle;
+            }
+        }
+        (from + to) / 2.0
+    }
+
+    fn set_runner_on_platform(&mut self, on_platform: bool) {
+        if on_platform != self.runner_on_platform {
+            self.runner_on_platform = on_platform;
+            self.event_channel.publish(if on_platform {
+                GameEvent::RunnerReturnedToPlatform
+            } else {
+                GameEvent::RunnerLeftPlatform
+            });
+        }
     }
 
     pub fn set_chart(&mut self, chart: RuntimeChart) {
+        // A replay's settings only apply to the run it was started on.
+        if let Some(player_settings) = self.player_settings.take() {
+            self.timing_windows = player_settings.timing_windows;
+            self.gauge_settings = player_settings.gauge_settings;
+            self.score_tracker.set_rule(player_settings.scoring_rule);
+        }
+
+        self.conductor.set_tempo_map(chart.tempo_map.clone());
+        self.platform_speed_map = Some(chart.create_runner_speed_map(1.0));
         self.chart = Some(chart);
+        self.reset_run();
+    }
+
+    /// Starts a fresh run of the current chart with the current settings.
+    fn reset_run(&mut self) {
+        if let Some(chart) = &self.chart {
+            let judgement_engine = JudgementEngine::new(chart.notes(), self.timing_windows);
+            self.score_tracker
+                .reset(judgement_engine.total_judgement_count());
+            self.judgement_engine = Some(judgement_engine);
+        }
+        self.results_recorder.clear();
+        self.replay_recorder.clear();
+        self.replay_player = None;
+        self.gauge = Gauge::new(self.gauge_settings);
+        self.runner_lateral_position = 0.0;
+        self.pending_steering.clear();
+        self.runner_on_platform = true;
+        self.runner_checked_until = 0.0;
+        self.run_ended = false;
+    }
+}
+
+/// First check time after `secs`.
+fn next_runner_check_time(secs: f32) -> f32 {
+    ((secs / RUNNER_CHECK_INTERVAL).floor() + 1.0) * RUNNER_CHECK_INTERVAL
+}
+
+/// Last check time up to and including `secs`.
+fn last_runner_check_time(secs: f32) -> f32 {
+    (secs / RUNNER_CHECK_INTERVAL).floor() * RUNNER_CHECK_INTERVAL
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chart::{modifiers::ChartModifiers, parse::parse_chart_file};
+
+    use super::{autoplay::Autoplay, clock::ManualClock, *};
+
+    const CHART_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../assets/charts/divine's_or_deal.czm"
+    );
+
+    /// Runs the chart on a manual clock advanced by `step` seconds, `steer` is called before every update.
+    fn run(
+        game_state: &mut GameState,
+        step: f32,
+        mut steer: impl FnMut(&mut GameState, f32),
+    ) -> RunResults {
+        let clock = ManualClock::new(0.0);
+        game_state
+            .conductor_mut()
+            .set_clock(Box::new(clock.clone()));
+
+        let end_time = game_state.chart().unwrap().end_offset() + 1.0;
+        let mut step_index = 0u32;
+        while !game_state.is_run_ended() && (step_index as f32 * step) < end_time {
+            step_index += 1;
+            let time = step_index as f32 * step;
+            clock.set_position(time);
+            steer(game_state, time);
+            game_state.update();
+        }
+
+        game_state.results().unwrap()
+    }
+
+    fn record_replay() -> Replay {
+        let mut game_state = GameState::new();
+        game_state.set_chart(parse_chart_file(CHART_PATH, &ChartModifiers::default()).unwrap());
+        let mut autoplay = Autoplay::new(game_state.chart().unwrap().notes(), 0.0);
+
+        run(&mut game_state, 1.0 / 60.0, |game_state, time| {
+            for input in autoplay.events_until(time) {
+                game_state.handle_input(input);
+            }
+
+            // Off the platform for a while, then drifting across the platform edges.
+            let lateral_position = if (3.0..4.0).contains(&time) {
+                10.0
+            } else if (5.0..8.0).contains(&time) {
+                (time * 3.0).sin() * 1.5
+            } else {
+                Autoplay::steering_position(&game_state.platform_extents(time)).unwrap_or(0.0)
+            };
+            game_state.steer_runner(SteeringEvent {
+                time,
+                lateral_position,
+            });
+        });
+
+        game_state.replay().unwrap()
+    }
+
+    fn play_replay(replay: &Replay, step: f32) -> RunResults {
+        let mut game_state = GameState::new();
+        game_state.set_chart(parse_chart_file(CHART_PATH, &replay.settings.modifiers).unwrap());
+        game_state.start_replay(replay.clone()).unwrap();
+
+        run(&mut game_state, step, |_, _| {})
+    }
+
+    #[test]
+    fn replay_results_do_not_depend_on_the_update_step() {
+        let replay = record_replay();
+
+        let fine_results = play_replay(&replay, 1.0 / 240.0);
+        let coarse_results = play_replay(&replay, 1.0 / 7.0);
+
+        assert!(fine_results.off_platform_secs > 1.0);
+        assert_eq!(
+            fine_results.to_json().unwrap(),
+            coarse_results.to_json().unwrap()
+        );
     }
 }
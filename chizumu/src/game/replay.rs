@@ -0,0 +1,187 @@
+/*!
+ * Recording of a run's inputs and their playback.
+ * The judgement engine and the runner only depend on the inputs and their song times, so feeding a replay's
+ * events back reproduces the exact same results.
+ */
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::chart::modifiers::ChartModifiers;
+
+use super::{
+    gauge::GaugeSettings, input::RhythmControlInput, judgement::JudgementInput,
+    judgement::TimingWindows,
+};
+
+/// Bumped whenever the replay file layout changes.
+/// 2 - chart modifiers in the settings, 3 - steering events.
+pub const REPLAY_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayEvent {
+    /// Song time in seconds.
+    pub time: f32,
+    pub input: RhythmControlInput,
+    pub pressed: bool,
+    pub cells: Option<(u32, u32)>,
+}
+
+impl From<&JudgementInput> for ReplayEvent {
+    fn from(input: &JudgementInput) -> Self {
+        Self {
+            time: input.time,
+            input: input.input,
+            pressed: input.pressed,
+            cells: input.cells,
+        }
+    }
+}
+
+impl From<&ReplayEvent> for JudgementInput {
+    fn from(event: &ReplayEvent) -> Self {
+        Self {
+            time: event.time,
+            input: event.input,
+            pressed: event.pressed,
+            cells: event.cells,
+            id: None,
+        }
+    }
+}
+
+/// A change of the runner's lateral position.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SteeringEvent {
+    /// Song time in seconds.
+    pub time: f32,
+    pub lateral_position: f32,
+}
+
+/// Settings that affect the results of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    pub timing_windows: TimingWindows,
+    pub gauge_settings: GaugeSettings,
+    pub scoring_rule: String,
+    /// The chart has to be built with the same modifiers to play the replay back.
+    pub modifiers: ChartModifiers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Hash of the chart file the replay was recorded on.
+    pub chart_hash: u64,
+    pub settings: ReplaySettings,
+    /// In order of song time.
+    pub events: Vec<ReplayEvent>,
+    /// In order of song time.
+    pub steering: Vec<SteeringEvent>,
+}
+
+impl Replay {
+    pub fn save(&self, file_path: &str) -> Result<()> {
+        std::fs::write(file_path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(file_path: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+
+        // Check the version first, files of other versions may not deserialize at all.
+        let version = value
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .ok_or_else(|| anyhow!("Replay file {} has no version", file_path))?;
+        if version != REPLAY_VERSION as u64 {
+            return Err(anyhow!(
+                "Unsupported replay version {}, expected {}",
+                version,
+                REPLAY_VERSION
+            ));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Records every input fed to the judgement engine and every steering change during a run.
+#[derive(Default)]
+pub struct ReplayRecorder {
+    events: Vec<ReplayEvent>,
+    steering: Vec<SteeringEvent>,
+}
+
+impl ReplayRecorder {
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.steering.clear();
+    }
+
+    pub fn record(&mut self, input: &JudgementInput) {
+        self.events.push(ReplayEvent::from(input));
+    }
+
+    pub fn record_steering(&mut self, event: SteeringEvent) {
+        self.steering.push(event);
+    }
+
+    pub fn create_replay(&self, chart_hash: u64, settings: ReplaySettings) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            chart_hash,
+            settings,
+            events: self.events.clone(),
+            steering: self.steering.clone(),
+        }
+    }
+}
+
+/// Hands out a replay's events as the song time reaches them.
+pub struct ReplayPlayer {
+    replay: Replay,
+    /// Index of the next event to be played.
+    next_event_index: usize,
+    /// Index of the next steering event to be played.
+    next_steering_index: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next_event_index: 0,
+            next_steering_index: 0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_event_index >= self.replay.events.len()
+    }
+
+    /// Takes the events that took place up to and including `time`.
+    pub fn events_until(&mut self, time: f32) -> Vec<JudgementInput> {
+        let events = &self.replay.events[self.next_event_index..];
+        let count = events.iter().take_while(|event| event.time <= time).count();
+        self.next_event_index += count;
+
+        events[..count].iter().map(JudgementInput::from).collect()
+    }
+
+    /// Takes the steering events that took place up to and including `time`.
+    pub fn steering_until(&mut self, time: f32) -> Vec<SteeringEvent> {
+        let steering = &self.replay.steering[self.next_steering_index..];
+        let count = steering
+            .iter()
+            .take_while(|event| event.time <= time)
+            .count();
+        self.next_steering_index += count;
+
+        steering[..count].to_vec()
+    }
+}
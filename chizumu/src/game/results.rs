@@ -0,0 +1,212 @@
+/*!
+ * Detailed results of a run, built from every recorded judgement.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::chart::{modifiers::ChartModifiers, runtime::RuntimeChart};
+
+use super::{
+    gauge::{ClearStatus, Gauge},
+    judgement::{Judgement, JudgementResult, NotePart},
+    score::{accuracy_weight, JudgementCounts, ScoreTracker},
+};
+
+/// Width of a single timing histogram bin in milliseconds.
+const HISTOGRAM_BIN_WIDTH_MS: f32 = 5.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramBin {
+    /// Lower bound of the bin, the bin covers [start_ms, start_ms + HISTOGRAM_BIN_WIDTH_MS).
+    pub start_ms: f32,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EarlyLateCounts {
+    pub judgement: Judgement,
+    pub early: usize,
+    pub late: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingStats {
+    pub mean_ms: f32,
+    pub std_dev_ms: f32,
+    pub histogram: Vec<HistogramBin>,
+    pub early_late: Vec<EarlyLateCounts>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MeasureAccuracy {
+    pub measure: u32,
+    /// Weighted accuracy percentage of the judgements of notes in the measure.
+    pub accuracy: f64,
+    pub judgements: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResults {
+    pub scoring_rule: String,
+    pub score: f64,
+    pub accuracy: f64,
+    pub max_combo: u32,
+    pub counts: JudgementCounts,
+    pub clear_status: ClearStatus,
+    pub gauge: f32,
+    /// Song time in seconds the runner spent off the platform.
+    pub off_platform_secs: f32,
+    pub modifiers: ChartModifiers,
+    pub timing: TimingStats,
+    /// Only measures that contain notes are listed.
+    pub measure_accuracy: Vec<MeasureAccuracy>,
+}
+
+impl RunResults {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn export(&self, file_path: &str) -> Result<()> {
+        std::fs::write(file_path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// Records every judgement of a run against the chart's notes.
+#[derive(Default)]
+pub struct ResultsRecorder {
+    results: Vec<JudgementResult>,
+    off_platform_secs: f32,
+}
+
+impl ResultsRecorder {
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.off_platform_secs = 0.0;
+    }
+
+    pub fn record(&mut self, result: &JudgementResult) {
+        self.results.push(*result);
+    }
+
+    pub fn record_off_platform(&mut self, secs: f32) {
+        self.off_platform_secs += secs;
+    }
+
+    pub fn results(&self) -> &[JudgementResult] {
+        &self.results
+    }
+
+    pub fn build(
+        &self,
+        chart: &RuntimeChart,
+        score_tracker: &ScoreTracker,
+        gauge: &Gauge,
+        clear_status: ClearStatus,
+    ) -> RunResults {
+        RunResults {
+            scoring_rule: String::from(score_tracker.rule_name()),
+            score: score_tracker.score(),
+            accuracy: score_tracker.accuracy(),
+            max_combo: score_tracker.max_combo(),
+            counts: *score_tracker.counts(),
+            clear_status,
+            gauge: gauge.value(),
+            off_platform_secs: self.off_platform_secs,
+            modifiers: *chart.modifiers(),
+            timing: self.timing_stats(),
+            measure_accuracy: self.measure_accuracy(chart),
+        }
+    }
+
+    /// Offsets of hit note heads, tails are left out as completed holds always have a zero offset.
+    fn hit_offsets(&self) -> impl Iterator<Item = (Judgement, f32)> + '_ {
+        self.results
+            .iter()
+            .filter(|result| result.part == NotePart::Head)
+            .filter_map(|result| result.offset_ms.map(|offset| (result.judgement, offset)))
+    }
+
+    fn timing_stats(&self) -> TimingStats {
+        let offsets = self
+            .hit_offsets()
+            .map(|(_, offset)| offset)
+            .collect::<Vec<_>>();
+
+        let (mean_ms, std_dev_ms) = if offsets.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let count = offsets.len() as f32;
+            let mean = offsets.iter().sum::<f32>() / count;
+            let variance = offsets.iter().map(|o| (o - mean).powi(2)).sum::<f32>() / count;
+            (mean, variance.sqrt())
+        };
+
+        let mut histogram = BTreeMap::<i32, usize>::new();
+        for offset in &offsets {
+            *histogram
+                .entry((offset / HISTOGRAM_BIN_WIDTH_MS).floor() as i32)
+                .or_default() += 1;
+        }
+
+        let early_late = [Judgement::Perfect, Judgement::Great, Judgement::Good]
+            .into_iter()
+            .map(|judgement| {
+                let (early, late) = self.hit_offsets().filter(|(j, _)| *j == judgement).fold(
+                    (0, 0),
+                    |(early, late), (_, offset)| {
+                        (
+                            early + (offset < 0.0) as usize,
+                            late + (offset > 0.0) as usize,
+                        )
+                    },
+                );
+                EarlyLateCounts {
+                    judgement,
+                    early,
+                    late,
+                }
+            })
+            .collect();
+
+        TimingStats {
+            mean_ms,
+            std_dev_ms,
+            histogram: histogram
+                .into_iter()
+                .map(|(bin, count)| HistogramBin {
+                    start_ms: bin as f32 * HISTOGRAM_BIN_WIDTH_MS,
+                    count,
+                })
+                .collect(),
+            early_late,
+        }
+    }
+
+    fn measure_accuracy(&self, chart: &RuntimeChart) -> Vec<MeasureAccuracy> {
+        let notes = chart.notes();
+
+        // Measure -> (weighted sum, judgement count).
+        let mut measures = BTreeMap::<u32, (f64, usize)>::new();
+        for result in &self.results {
+            let note_offset = notes[result.note_index].offset;
+            let measure = chart.tempo_map.seconds_to_position(note_offset).max(0.0) as u32;
+            let entry = measures.entry(measure).or_default();
+            entry.0 += accuracy_weight(result.judgement);
+            entry.1 += 1;
+        }
+
+        measures
+            .into_iter()
+            .map(|(measure, (weighted_sum, judgements))| MeasureAccuracy {
+                measure,
+                accuracy: 100.0 * weighted_sum / judgements as f64,
+                judgements,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,206 @@
+/*!
+ * Score, combo and accuracy accumulated from judgement results.
+ */
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use super::judgement::{Judgement, JudgementResult};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct JudgementCounts {
+    pub perfect: usize,
+    pub great: usize,
+    pub good: usize,
+    pub miss: usize,
+}
+
+impl JudgementCounts {
+    pub fn add(&mut self, judgement: Judgement) {
+        match judgement {
+            Judgement::Perfect => self.perfect += 1,
+            Judgement::Great => self.great += 1,
+            Judgement::Good => self.good += 1,
+            Judgement::Miss => self.miss += 1,
+        }
+    }
+
+    pub fn get(&self, judgement: Judgement) -> usize {
+        match judgement {
+            Judgement::Perfect => self.perfect,
+            Judgement::Great => self.great,
+            Judgement::Good => self.good,
+            Judgement::Miss => self.miss,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.perfect + self.great + self.good + self.miss
+    }
+
+    /// Sum of `weight` over all counted judgements.
+    fn weighted_sum(&self, weight: impl Fn(Judgement) -> f64) -> f64 {
+        [
+            Judgement::Perfect,
+            Judgement::Great,
+            Judgement::Good,
+            Judgement::Miss,
+        ]
+        .into_iter()
+        .map(|judgement| self.get(judgement) as f64 * weight(judgement))
+        .sum()
+    }
+}
+
+/// Weight of each judgement towards accuracy.
+pub fn accuracy_weight(judgement: Judgement) -> f64 {
+    match judgement {
+        Judgement::Perfect => 1.0,
+        Judgement::Great => 0.75,
+        Judgement::Good => 0.5,
+        Judgement::Miss => 0.0,
+    }
+}
+
+/// Computes the score of a run.
+pub trait ScoringRule {
+    fn name(&self) -> &'static str;
+
+    /// `total_judgements` is the number of judgements in the whole chart, so the maximum score is only reached
+    /// once every note is judged.
+    fn score(&self, counts: &JudgementCounts, total_judgements: usize) -> f64;
+}
+
+/// Score out of 1,010,000 where perfects are worth 101% of a note's base value.
+pub struct MillionScoring;
+
+impl MillionScoring {
+    const BASE_SCORE: f64 = 1_000_000.0;
+
+    fn weight(judgement: Judgement) -> f64 {
+        match judgement {
+            Judgement::Perfect => 1.01,
+            Judgement::Great => 0.75,
+            Judgement::Good => 0.5,
+            Judgement::Miss => 0.0,
+        }
+    }
+}
+
+impl ScoringRule for MillionScoring {
+    fn name(&self) -> &'static str {
+        "million"
+    }
+
+    fn score(&self, counts: &JudgementCounts, total_judgements: usize) -> f64 {
+        if total_judgements == 0 {
+            return 0.0;
+        }
+        (Self::BASE_SCORE * counts.weighted_sum(Self::weight) / total_judgements as f64).floor()
+    }
+}
+
+/// Score as an accuracy percentage over the whole chart.
+pub struct AccuracyScoring;
+
+impl ScoringRule for AccuracyScoring {
+    fn name(&self) -> &'static str {
+        "accuracy"
+    }
+
+    fn score(&self, counts: &JudgementCounts, total_judgements: usize) -> f64 {
+        if total_judgements == 0 {
+            return 0.0;
+        }
+        100.0 * counts.weighted_sum(accuracy_weight) / total_judgements as f64
+    }
+}
+
+/// Looks up a built-in scoring rule by its name.
+pub fn scoring_rule_from_name(name: &str) -> Result<Box<dyn ScoringRule>> {
+    match name {
+        "million" => Ok(Box::new(MillionScoring)),
+        "accuracy" => Ok(Box::new(AccuracyScoring)),
+        _ => Err(anyhow!("Unknown scoring rule `{}`", name)),
+    }
+}
+
+/// Accumulates judgement results of a run.
+pub struct ScoreTracker {
+    rule: Box<dyn ScoringRule>,
+    total_judgements: usize,
+
+    counts: JudgementCounts,
+    combo: u32,
+    max_combo: u32,
+}
+
+impl ScoreTracker {
+    pub fn new(rule: Box<dyn ScoringRule>) -> Self {
+        Self {
+            rule,
+            total_judgements: 0,
+            counts: JudgementCounts::default(),
+            combo: 0,
+            max_combo: 0,
+        }
+    }
+
+    /// Clears the results for a new run of a chart with `total_judgements` judgements.
+    pub fn reset(&mut self, total_judgements: usize) {
+        self.total_judgements = total_judgements;
+        self.counts = JudgementCounts::default();
+        self.combo = 0;
+        self.max_combo = 0;
+    }
+
+    /// The score is derived from the counts, so the rule can be changed at any point of the run.
+    /// Returns the previous rule.
+    pub fn set_rule(&mut self, rule: Box<dyn ScoringRule>) -> Box<dyn ScoringRule> {
+        std::mem::replace(&mut self.rule, rule)
+    }
+
+    pub fn add_result(&mut self, result: &JudgementResult) {
+        self.counts.add(result.judgement);
+
+        if result.judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+    }
+
+    pub fn rule_name(&self) -> &'static str {
+        self.rule.name()
+    }
+
+    pub fn score(&self) -> f64 {
+        self.rule.score(&self.counts, self.total_judgements)
+    }
+
+    pub fn counts(&self) -> &JudgementCounts {
+        &self.counts
+    }
+
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn max_combo(&self) -> u32 {
+        self.max_combo
+    }
+
+    /// Weighted accuracy percentage of the notes judged so far, 100 if nothing is judged yet.
+    pub fn accuracy(&self) -> f64 {
+        let judged = self.counts.total();
+        if judged == 0 {
+            return 100.0;
+        }
+        100.0 * self.counts.weighted_sum(accuracy_weight) / judged as f64
+    }
+
+    pub fn is_full_combo(&self) -> bool {
+        self.counts.miss == 0 && self.counts.total() == self.total_judgements
+    }
+}
@@ -0,0 +1,202 @@
+/*!
+ * Local store of the best results per chart, kept as a single JSON file in the user's data directory.
+ */
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::chart::modifiers::ChartModifiers;
+
+use super::{gauge::ClearStatus, results::RunResults};
+
+/// Bumped whenever the file layout changes, with a migration added to `MIGRATIONS`.
+const SCHEMA_VERSION: u32 = 2;
+
+/// Migrations of the raw file contents, `MIGRATIONS[i]` upgrades a file from schema version `i + 1` to `i + 2`.
+const MIGRATIONS: &[fn(&mut serde_json::Value) -> Result<()>] = &[migrate_best_scores_per_rule];
+
+const SCORE_DATABASE_FILE_NAME: &str = "scores.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BestScore {
+    pub score: f64,
+    /// Modifiers used for the score.
+    pub modifiers: ChartModifiers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreRecord {
+    /// Keyed by scoring rule name, scores of different rules are not comparable.
+    pub best_scores: BTreeMap<String, BestScore>,
+    pub best_combo: u32,
+    pub best_clear_status: ClearStatus,
+    pub play_count: u32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ScoreDatabaseFile {
+    schema_version: u32,
+    /// Keyed by chart hash and difficulty.
+    records: BTreeMap<String, ScoreRecord>,
+}
+
+pub struct ScoreDatabase {
+    file_path: PathBuf,
+    records: BTreeMap<String, ScoreRecord>,
+}
+
+impl ScoreDatabase {
+    /// Opens the database in the user's data directory.
+    pub fn open_default() -> Result<Self> {
+        let data_dir =
+            dirs::data_dir().ok_or_else(|| anyhow!("No user data directory available"))?;
+        Self::open(data_dir.join("chizumu").join(SCORE_DATABASE_FILE_NAME))
+    }
+
+    /// Starts an empty database if the file does not exist yet.
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        let records = if file_path.exists() {
+            Self::load_records(&file_path)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { file_path, records })
+    }
+
+    fn load_records(file_path: &Path) -> Result<BTreeMap<String, ScoreRecord>> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+
+        let version = value
+            .get("schema_version")
+            .and_then(|version| version.as_u64())
+            .ok_or_else(|| anyhow!("Score database {:?} has no schema version", file_path))?
+            as u32;
+        if version == 0 || version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Unsupported score database schema version {}, expected at most {}",
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+
+        for migration in &MIGRATIONS[(version - 1) as usize..] {
+            migration(&mut value)?;
+        }
+        if version < SCHEMA_VERSION {
+            log::info!(
+                "Migrated score database from schema version {} to {}",
+                version,
+                SCHEMA_VERSION
+            );
+        }
+
+        let file: ScoreDatabaseFile = serde_json::from_value(value)?;
+        Ok(file.records)
+    }
+
+    /// Writes to a temporary file first so a failed write does not lose existing records.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = ScoreDatabaseFile {
+            schema_version: SCHEMA_VERSION,
+            records: self.records.clone(),
+        };
+        let temp_file_path = self.file_path.with_extension("json.tmp");
+        std::fs::write(&temp_file_path, serde_json::to_string_pretty(&file)?)?;
+        std::fs::rename(&temp_file_path, &self.file_path)?;
+
+        Ok(())
+    }
+
+    fn record_key(chart_hash: u64, difficulty: &str) -> String {
+        format!("{:016x}:{}", chart_hash, difficulty)
+    }
+
+    pub fn record(&self, chart_hash: u64, difficulty: &str) -> Option<&ScoreRecord> {
+        self.records.get(&Self::record_key(chart_hash, difficulty))
+    }
+
+    /// Counts the play and keeps the best of each value, returns whether the score is a new best of its scoring rule.
+    pub fn add_run(&mut self, chart_hash: u64, difficulty: &str, results: &RunResults) -> bool {
+        let record = self
+            .records
+            .entry(Self::record_key(chart_hash, difficulty))
+            .or_insert_with(|| ScoreRecord {
+                best_scores: BTreeMap::new(),
+                best_combo: 0,
+                best_clear_status: ClearStatus::InProgress,
+                play_count: 0,
+            });
+
+        record.play_count += 1;
+        record.best_combo = record.best_combo.max(results.max_combo);
+        if clear_status_rank(results.clear_status) > clear_status_rank(record.best_clear_status) {
+            record.best_clear_status = results.clear_status;
+        }
+
+        let new_best = record
+            .best_scores
+            .get(&results.scoring_rule)
+            .map_or(true, |best_score| results.score > best_score.score);
+        if new_best {
+            record.best_scores.insert(
+                results.scoring_rule.clone(),
+                BestScore {
+                    score: results.score,
+                    modifiers: results.modifiers,
+                },
+            );
+        }
+        new_best
+    }
+}
+
+/// Schema version 1 kept a single best score, replaced by scores of any other scoring rule.
+fn migrate_best_scores_per_rule(value: &mut serde_json::Value) -> Result<()> {
+    let records = value
+        .get_mut("records")
+        .and_then(|records| records.as_object_mut())
+        .ok_or_else(|| anyhow!("Score database has no records"))?;
+
+    for record in records.values_mut() {
+        let record = record
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Score record is not an object"))?;
+        let (Some(score), Some(scoring_rule), Some(modifiers)) = (
+            record.remove("best_score"),
+            record.remove("scoring_rule"),
+            record.remove("modifiers"),
+        ) else {
+            return Err(anyhow!("Score record is missing its best score"));
+        };
+        let scoring_rule = scoring_rule
+            .as_str()
+            .ok_or_else(|| anyhow!("Scoring rule is not a string"))?
+            .to_owned();
+
+        record.insert(
+            String::from("best_scores"),
+            serde_json::json!({ scoring_rule: { "score": score, "modifiers": modifiers } }),
+        );
+    }
+
+    Ok(())
+}
+
+fn clear_status_rank(clear_status: ClearStatus) -> u32 {
+    match clear_status {
+        ClearStatus::InProgress => 0,
+        ClearStatus::Failed => 1,
+        ClearStatus::Cleared => 2,
+    }
+}
@@ -0,0 +1,7 @@
+/*!
+ * Chart and game logic shared by the game and the headless simulation.
+ * Does not depend on audio, input devices or a window, those are provided by the binaries.
+ */
+
+pub mod chart;
+pub mod game;
@@ -1,28 +1,106 @@
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::{
     dpi,
-    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{CursorGrabMode, Window, WindowBuilder},
 };
 
 use chizumu_rendering::renderer::Renderer;
 
-use crate::chart::parse::parse_chart_file;
-use crate::chart::runtime;
-use crate::game::conductor::Conductor;
-use crate::game::GameState;
-use crate::{core::audio::AudioSystem, core::input::RhythmControlInputHandler};
+use chizumu::chart::modifiers::ChartModifiers;
+
+use crate::core::audio::AudioTrack;
+use crate::scene::SceneManager;
 
-mod chart;
 mod core;
-mod game;
+mod scene;
+
+/// Options given on the command line.
+#[derive(Default)]
+struct LaunchOptions {
+    /// Replay file played back instead of taking live input.
+    replay_path: Option<String>,
+    /// File the replay of the run is saved to once the run ends.
+    save_replay_path: Option<String>,
+    /// Starts the chart with autoplay on.
+    autoplay: bool,
+    /// Logs the latency of every press and reports percentiles when the chart ends.
+    latency_diagnostics: bool,
+    /// Volumes of the mixer tracks that are not left at 1.0.
+    track_volumes: Vec<(AudioTrack, f64)>,
+    modifiers: ChartModifiers,
+}
 
-fn main() {
+impl LaunchOptions {
+    fn from_args() -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for argument `{}`", arg))
+            };
+            match arg.as_str() {
+                "--replay" => options.replay_path = Some(value()?),
+                "--save-replay" => options.save_replay_path = Some(value()?),
+                "--autoplay" => options.autoplay = true,
+                "--latency-diagnostics" => options.latency_diagnostics = true,
+                "--music-volume" => options
+                    .track_volumes
+                    .push((AudioTrack::Music, value()?.parse()?)),
+                "--hitsound-volume" => options
+                    .track_volumes
+                    .push((AudioTrack::Hitsounds, value()?.parse()?)),
+                "--ui-volume" => options
+                    .track_volumes
+                    .push((AudioTrack::Ui, value()?.parse()?)),
+                "--mirror" => options.modifiers.mirror = true,
+                "--random" => options.modifiers.random_seed = Some(value()?.parse()?),
+                "--hidden" => {
+                    options.modifiers.hidden_distance = Some(parse_positive(&arg, &value()?)?)
+                }
+                "--sudden" => {
+                    options.modifiers.sudden_distance = Some(parse_positive(&arg, &value()?)?)
+                }
+                "--speed" => options.modifiers.speed_multiplier = parse_positive(&arg, &value()?)?,
+                _ => return Err(anyhow!("Unknown argument `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Distances and multipliers have to be positive and finite.
+fn parse_positive(arg: &str, value: &str) -> Result<f32> {
+    let parsed: f32 = value.parse()?;
+    if !(parsed.is_finite() && parsed > 0.0) {
+        return Err(anyhow!(
+            "Value of argument `{}` has to be positive, got {}",
+            arg,
+            value
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Locks the cursor in place where supported and confines it to the window otherwise.
+fn set_cursor_grabbed(window: &Window, grabbed: bool) {
+    let result = if grabbed {
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(err) = result {
+        log::error!("Failed to change the cursor grab: {}", err);
+    }
+    window.set_cursor_visible(!grabbed);
+}
+
+fn main() -> Result<()> {
     let env = env_logger::Env::default()
         .filter_or("MY_LOG_LEVEL", "trace")
         .write_style_or("MY_LOG_STYLE", "always");
@@ -30,97 +108,67 @@ fn main() {
 
     log::info!("Starting Chizumu...");
 
+    let launch_options = LaunchOptions::from_args()?;
+
     // Initialize window.
-    let event_loop = EventLoop::new().unwrap();
+    let event_loop = EventLoop::new()?;
     let window = WindowBuilder::new()
         .with_title("Chizumu")
         .with_inner_size(dpi::PhysicalSize::new(1920, 1200))
         .with_position(dpi::PhysicalPosition::new(100, 100))
-        .build(&event_loop)
-        .unwrap();
+        .build(&event_loop)?;
 
     // Initialize renderer.
-    let mut renderer = Renderer::new(
-        window.window_handle().unwrap().as_raw(),
-        window.display_handle().unwrap().as_raw(),
-    )
-    .unwrap();
-
-    // Initialize audio system.
-    let mut audio_system = AudioSystem::new().unwrap();
-
-    // Initialize rhythm control (game) input handler.
-    let input_handler = RhythmControlInputHandler::new();
-
-    // Parse chart file.
-    let runtime_chart = parse_chart_file("assets/charts/lateral_arc_of_flame.czm").unwrap();
-    let runner_speed = 7.0;
-
-    // Create renderer resources based on the parsed chart.
-    renderer
-        .set_platform_objects(runtime_chart.create_platform_objects(runner_speed))
-        .unwrap();
-    renderer.add_hit_objects(&runtime_chart.create_hit_objects());
-
-    // Load chart music.
-    let music_index = audio_system
-        .load_music_data(&runtime_chart.chart_info.music_file_path)
-        .unwrap();
-
-    // Initialize game/player state.
-    let mut game_state = GameState::new();
-    game_state.set_chart(runtime_chart);
-
-    // Connductor keeps track of the current music position.
-    let mut conductor = Conductor::new();
-
-    let mut last_music_position = 0.0;
-    let mut last_frame_time = Instant::now();
-
-    // Start the music.
-    conductor
-        .start_music(&mut audio_system, music_index)
-        .unwrap();
-
-    event_loop
-        .run(move |event, eltw| {
-            eltw.set_control_flow(ControlFlow::Poll);
-
-            match event {
-                Event::WindowEvent { event, .. } => {
-                    input_handler.handle_window_event(&event);
-                    match event {
-                        WindowEvent::CloseRequested => {
-                            eltw.exit();
-                        }
-                        WindowEvent::Resized(_) => {
-                            // XXX: Explicitly tell the swapchain(held by `Device`) to be recreated/resized.
-                        }
-                        WindowEvent::RedrawRequested => {
-                            renderer.render().unwrap();
-                        }
-                        _ => (),
-                    }
+    let renderer = Renderer::new(
+        window.window_handle()?.as_raw(),
+        window.display_handle()?.as_raw(),
+    )?;
+
+    // Scenes own the renderer, audio and game state from here on.
+    let mut scene_manager = SceneManager::new(renderer, window.inner_size(), launch_options)?;
+    let mut cursor_grabbed = false;
+
+    event_loop.run(move |event, eltw| {
+        eltw.set_control_flow(ControlFlow::Poll);
+
+        let result = match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    eltw.exit();
+                    Ok(())
+                }
+                WindowEvent::Resized(_) => {
+                    // XXX: Explicitly tell the swapchain(held by `Device`) to be recreated/resized.
+                    scene_manager.handle_window_event(&event)
+                }
+                WindowEvent::RedrawRequested => scene_manager.render(),
+                _ => scene_manager.handle_window_event(&event),
+            },
+            Event::DeviceEvent { event, .. } => {
+                scene_manager.handle_device_event(&event);
+                Ok(())
+            }
+            Event::AboutToWait => {
+                let result = scene_manager.update();
+                if scene_manager.is_exit_requested() {
+                    eltw.exit();
+                }
+                if scene_manager.is_cursor_grabbed() != cursor_grabbed {
+                    cursor_grabbed = !cursor_grabbed;
+                    set_cursor_grabbed(&window, cursor_grabbed);
                 }
-                Event::AboutToWait => {
-                    let now = Instant::now();
-                    let frame_dt = now - last_frame_time;
-                    last_frame_time = now;
-
-                    let current_music_position = conductor.get_current_music_position().unwrap();
-                    let music_dt = current_music_position - last_music_position;
-                    last_music_position = current_music_position;
 
-                    renderer
-                        .update(frame_dt.as_secs_f32(), music_dt * runner_speed)
-                        .unwrap();
+                window.request_redraw();
+                result
+            }
+            _ => Ok(()),
+        };
 
-                    game_state.update_current_music_position(current_music_position);
+        if let Err(err) = result {
+            log::error!("Exiting on error: {}", err);
+            eltw.exit();
+        }
+    })?;
 
-                    window.request_redraw();
-                }
-                _ => (),
-            }
-        })
-        .unwrap();
+    Ok(())
 }
@@ -0,0 +1,957 @@
+/*!
+ * Scenes of the game and the transitions between them.
+ * Title -> Song Select -> Loading -> Playing <-> Paused -> Results -> Song Select.
+ * Song Select <-> Rebinding.
+ */
+
+use std::{sync::mpsc::Receiver, time::Instant};
+
+use anyhow::{anyhow, Result};
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, ElementState, KeyEvent, TouchPhase, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use chizumu::chart::{parse::parse_chart_file, runtime::RunnerSpeedMap};
+use chizumu::game::{
+    autoplay::Autoplay,
+    events::GameEvent,
+    judgement::{Judgement, JudgementInput},
+    replay::{Replay, SteeringEvent},
+    results::RunResults,
+    score_db::ScoreDatabase,
+    GameState,
+};
+use chizumu_rendering::renderer::Renderer;
+use nalgebra::{Vector3, Vector4};
+
+use crate::core::{
+    audio::{AudioClock, AudioSystem},
+    gamepad::{GamepadEvent, GamepadInput},
+    hitsounds::{find_hitsound_packs, HitsoundKind, HitsoundPack},
+    input::{RhythmControlInput, RhythmControlInputHandler},
+    keybindings::Keybindings,
+    latency::LatencyDiagnostics,
+    menu_sounds::{MenuSound, MenuSounds},
+    settings::Settings,
+    steering::RunnerSteering,
+    touch::TouchLayout,
+};
+use crate::LaunchOptions;
+
+const CHARTS_DIRECTORY: &str = "assets/charts";
+const CHART_FILE_EXTENSION: &str = "czm";
+
+/// Distance covered by the runner per second before the speed multiplier modifier.
+const RUNNER_SPEED: f32 = 7.0;
+
+/// Toggles autoplay mid-song.
+const AUTOPLAY_TOGGLE_KEY: KeyCode = KeyCode::F2;
+
+/// Starts rebinding the rhythm control inputs from the song select.
+const REBINDING_KEY: KeyCode = KeyCode::F3;
+
+/// Switches between the regular keybindings and the lane layout from the song select.
+const LANE_LAYOUT_TOGGLE_KEY: KeyCode = KeyCode::F4;
+
+/// Switches to the next hitsound pack from the song select.
+const HITSOUND_PACK_KEY: KeyCode = KeyCode::F5;
+
+/// Color missed notes are dimmed to.
+const MISSED_NOTE_COLOR: Vector3<f32> = Vector3::new(0.3, 0.3, 0.3);
+
+const RUNNER_ON_PLATFORM_COLOR: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
+const RUNNER_OFF_PLATFORM_COLOR: Vector4<f32> = Vector4::new(1.0, 0.2, 0.2, 1.0);
+
+fn judgement_color(judgement: Judgement) -> Vector4<f32> {
+    match judgement {
+        Judgement::Perfect => Vector4::new(1.0, 0.85, 0.3, 1.0),
+        Judgement::Great => Vector4::new(0.4, 0.9, 1.0, 1.0),
+        Judgement::Good => Vector4::new(0.4, 1.0, 0.5, 1.0),
+        Judgement::Miss => Vector4::new(0.5, 0.5, 0.5, 1.0),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Scene {
+    Title,
+    SongSelect,
+    /// The chart is loaded on the next update.
+    Loading {
+        chart_path: String,
+    },
+    Playing,
+    Paused,
+    Results,
+    /// Bindings are captured for `RhythmControlInput::ALL[input_index]`.
+    Rebinding {
+        input_index: usize,
+    },
+}
+
+/// Resources of the loaded chart.
+struct PlaySession {
+    runner_speed_map: RunnerSpeedMap,
+    /// Plays the chart through the input handler, the same path as physical inputs.
+    autoplay: Option<Autoplay>,
+    /// Runs played back or assisted by autoplay are not saved as scores.
+    autoplay_used: bool,
+}
+
+/// Owns the active scene and the resources shared between scenes, routes window events to the active scene.
+pub(crate) struct SceneManager {
+    scene: Scene,
+    exit_requested: bool,
+
+    launch_options: LaunchOptions,
+    chart_paths: Vec<String>,
+    selected_chart_index: usize,
+
+    renderer: Renderer,
+    audio_system: AudioSystem,
+    /// `None` if no pack could be loaded, hits are silent then.
+    hitsound_pack: Option<HitsoundPack>,
+    menu_sounds: MenuSounds,
+    keybindings: Keybindings,
+    /// Edited copy of the keybindings while rebinding, applied once every input is done.
+    rebinding_keybindings: Option<Keybindings>,
+    settings: Settings,
+    input_handler: RhythmControlInputHandler,
+    /// `None` if gamepads are not supported on the platform.
+    gamepad_input: Option<GamepadInput>,
+    steering: RunnerSteering,
+    /// Set when enabled by the launch options.
+    latency_diagnostics: Option<LatencyDiagnostics>,
+    game_state: GameState,
+    game_events: Receiver<GameEvent>,
+
+    /// Set while a chart is loaded.
+    session: Option<PlaySession>,
+    last_frame_time: Instant,
+}
+
+impl SceneManager {
+    /// `window_size` - in physical pixels, for touch input.
+    pub(crate) fn new(
+        renderer: Renderer,
+        window_size: PhysicalSize<u32>,
+        launch_options: LaunchOptions,
+    ) -> Result<Self> {
+        let chart_paths = find_chart_files()?;
+        log::info!("Found {} charts", chart_paths.len());
+
+        let keybindings = Keybindings::default_file_path()
+            .and_then(|file_path| Keybindings::load(&file_path))
+            .unwrap_or_else(|err| {
+                log::error!(
+                    "Failed to load the keybindings, using the defaults: {}",
+                    err
+                );
+                Keybindings::default()
+            });
+        let mut settings = Settings::default_file_path()
+            .and_then(|file_path| Settings::load(&file_path))
+            .unwrap_or_else(|err| {
+                log::error!("Failed to load the settings, using the defaults: {}", err);
+                Settings::default()
+            });
+        if let Err(err) = settings.lane_layout().check_keybindings(&keybindings) {
+            log::error!("Turning the lane layout off: {}", err);
+            // A disabled layout does not conflict with any keybindings.
+            settings.set_lane_layout_enabled(false, &keybindings)?;
+        }
+
+        let gamepad_input = GamepadInput::new()
+            .map_err(|err| log::error!("{}", err))
+            .ok();
+
+        let latency_diagnostics = launch_options
+            .latency_diagnostics
+            .then(LatencyDiagnostics::new);
+
+        let audio_system = AudioSystem::new()?;
+        for (track, volume) in &launch_options.track_volumes {
+            audio_system.set_track_volume(*track, *volume)?;
+        }
+
+        let hitsound_pack = HitsoundPack::load(&audio_system, settings.hitsound_pack())
+            .map_err(|err| {
+                log::error!(
+                    "Failed to load the hitsound pack {}: {}",
+                    settings.hitsound_pack(),
+                    err
+                )
+            })
+            .ok();
+
+        let menu_sounds = MenuSounds::load(&audio_system);
+
+        let mut game_state = GameState::new();
+        let game_events = game_state.subscribe_events();
+
+        Ok(Self {
+            scene: Scene::Title,
+            exit_requested: false,
+            launch_options,
+            chart_paths,
+            selected_chart_index: 0,
+            audio_system,
+            hitsound_pack,
+            menu_sounds,
+            input_handler: RhythmControlInputHandler::new(
+                &keybindings,
+                &settings,
+                TouchLayout::new(renderer.screen_to_playfield(), window_size),
+            ),
+            keybindings,
+            rebinding_keybindings: None,
+            settings,
+            gamepad_input,
+            steering: RunnerSteering::new(),
+            latency_diagnostics,
+            game_state,
+            game_events,
+            renderer,
+            session: None,
+            last_frame_time: Instant::now(),
+        })
+    }
+
+    pub(crate) fn is_exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    fn transition(&mut self, scene: Scene) {
+        log::info!("Scene {:?} -> {:?}", self.scene, scene);
+        self.scene = scene;
+
+        match self.scene {
+            Scene::SongSelect => self.log_selected_chart(),
+            Scene::Rebinding { input_index } => self.log_rebinding_input(input_index),
+            _ => {}
+        }
+    }
+
+    fn play_menu_sound(&self, sound: MenuSound) {
+        if let Err(err) = self.menu_sounds.play(sound) {
+            log::error!("Failed to play the menu sound: {}", err);
+        }
+    }
+
+    /// Raw mouse motion drives the mouse axis while playing, the cursor should be grabbed and hidden meanwhile so
+    /// it neither leaves the window nor clicks outside of it.
+    pub(crate) fn is_cursor_grabbed(&self) -> bool {
+        self.scene == Scene::Playing
+    }
+
+    /// Raw mouse motion drives the mouse axis while playing.
+    pub(crate) fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let Scene::Playing = self.scene {
+            self.input_handler.handle_device_event(event);
+        }
+    }
+
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent) -> Result<()> {
+        // Resizes are also forwarded outside of play to keep the touch layout up to date, releases so no input
+        // stays pressed, e.g. a key released while paused.
+        if matches!(self.scene, Scene::Playing | Scene::Rebinding { .. })
+            || matches!(event, WindowEvent::Resized(_))
+            || is_release(event)
+        {
+            self.input_handler.handle_window_event(event);
+        }
+        // Also outside of play so steering keys released in the meantime do not stay held.
+        self.steering.handle_window_event(event);
+
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(keycode),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.handle_key_press(*keycode)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_key_press(&mut self, keycode: KeyCode) -> Result<()> {
+        match (&self.scene, keycode) {
+            (Scene::Title, KeyCode::Enter) => {
+                self.play_menu_sound(MenuSound::Confirm);
+                self.transition(Scene::SongSelect);
+            }
+            (Scene::Title, KeyCode::Escape) => self.exit_requested = true,
+
+            (Scene::SongSelect, KeyCode::ArrowUp | KeyCode::ArrowDown) => {
+                if !self.chart_paths.is_empty() {
+                    let count = self.chart_paths.len();
+                    self.selected_chart_index = if keycode == KeyCode::ArrowUp {
+                        (self.selected_chart_index + count - 1) % count
+                    } else {
+                        (self.selected_chart_index + 1) % count
+                    };
+                    self.play_menu_sound(MenuSound::Move);
+                    self.log_selected_chart();
+                }
+            }
+            (Scene::SongSelect, KeyCode::Enter) => {
+                if let Some(chart_path) = self.chart_paths.get(self.selected_chart_index) {
+                    let chart_path = chart_path.clone();
+                    self.play_menu_sound(MenuSound::Confirm);
+                    self.transition(Scene::Loading { chart_path });
+                }
+            }
+            (Scene::SongSelect, KeyCode::Escape) => {
+                self.play_menu_sound(MenuSound::Back);
+                self.transition(Scene::Title);
+            }
+            (Scene::SongSelect, REBINDING_KEY) => {
+                self.rebinding_keybindings = Some(self.keybindings.clone());
+                self.input_handler.start_binding_capture();
+                self.log_lane_layout();
+                self.transition(Scene::Rebinding { input_index: 0 });
+            }
+            (Scene::SongSelect, LANE_LAYOUT_TOGGLE_KEY) => self.toggle_lane_layout(),
+            (Scene::SongSelect, HITSOUND_PACK_KEY) => self.select_next_hitsound_pack(),
+
+            (Scene::Playing, KeyCode::Escape) => {
+                self.game_state.conductor_mut().pause()?;
+                self.transition(Scene::Paused);
+            }
+            (Scene::Playing, AUTOPLAY_TOGGLE_KEY) => self.toggle_autoplay(),
+
+            (Scene::Paused, KeyCode::Escape | KeyCode::Enter) => {
+                self.game_state.conductor_mut().resume()?;
+                self.transition(Scene::Playing);
+            }
+            (Scene::Paused, KeyCode::Backspace) => {
+                self.play_menu_sound(MenuSound::Back);
+                self.unload_chart()?;
+                self.transition(Scene::SongSelect);
+            }
+
+            (Scene::Results, KeyCode::Enter | KeyCode::Escape) => {
+                self.play_menu_sound(MenuSound::Confirm);
+                self.unload_chart()?;
+                self.transition(Scene::SongSelect);
+            }
+
+            (&Scene::Rebinding { input_index }, KeyCode::Enter) => {
+                self.next_rebinding_input(input_index)
+            }
+            (&Scene::Rebinding { input_index }, KeyCode::Backspace) => {
+                if let Some(keybindings) = self.rebinding_keybindings.as_mut() {
+                    keybindings.clear(RhythmControlInput::ALL[input_index]);
+                }
+                self.log_rebinding_input(input_index);
+            }
+            (Scene::Rebinding { .. }, KeyCode::Escape) => {
+                log::info!("Rebinding cancelled, keybindings are unchanged");
+                self.play_menu_sound(MenuSound::Back);
+                self.rebinding_keybindings = None;
+                self.input_handler.end_binding_capture();
+                self.transition(Scene::SongSelect);
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn update(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let frame_dt = now - self.last_frame_time;
+        self.last_frame_time = now;
+
+        self.poll_gamepads();
+
+        match &self.scene {
+            Scene::Loading { chart_path } => {
+                let chart_path = chart_path.clone();
+                match self.load_chart(&chart_path) {
+                    Ok(()) => self.transition(Scene::Playing),
+                    Err(err) => {
+                        log::error!("Failed to load chart {}: {}", chart_path, err);
+                        self.unload_chart()?;
+                        self.transition(Scene::SongSelect);
+                    }
+                }
+            }
+            &Scene::Rebinding { input_index } => self.update_rebinding(input_index),
+            Scene::Playing => {
+                self.update_playing(frame_dt.as_secs_f32());
+                if self.game_state.is_run_ended() {
+                    // The chart can be over before the music is, the results are shown without it.
+                    self.game_state.conductor_mut().stop()?;
+                    self.report_results();
+                    self.transition(Scene::Results);
+                }
+            }
+            _ => {}
+        }
+
+        // Every renderer component is positioned from the absolute music position to stay in sync.
+        let runner_position = match (
+            &self.session,
+            self.game_state.conductor().get_current_music_position(),
+        ) {
+            (Some(session), Some(current_music_position)) => session
+                .runner_speed_map
+                .runner_position(current_music_position),
+            _ => 0.0,
+        };
+        self.renderer
+            .update(frame_dt.as_secs_f32(), runner_position)?;
+
+        Ok(())
+    }
+
+    /// Gamepad presses are handled during play only, the same as window events.
+    /// Releases are always handled so no input stays pressed.
+    fn poll_gamepads(&mut self) {
+        let Some(gamepad_input) = self.gamepad_input.as_mut() else {
+            return;
+        };
+
+        for event in gamepad_input.poll(&self.keybindings) {
+            match event {
+                GamepadEvent::Button {
+                    gamepad_id,
+                    button,
+                    input,
+                    pressed,
+                    os_event,
+                } => {
+                    if pressed && self.scene != Scene::Playing {
+                        continue;
+                    }
+                    let state = if pressed {
+                        ElementState::Pressed
+                    } else {
+                        ElementState::Released
+                    };
+                    self.input_handler
+                        .handle_gamepad_button(gamepad_id, button, input, state, os_event);
+                }
+                GamepadEvent::Steering(axis) => self.steering.set_stick_axis(axis),
+                GamepadEvent::Disconnected {
+                    gamepad_id,
+                    os_event,
+                } => self.input_handler.release_gamepad(gamepad_id, os_event),
+            }
+        }
+    }
+
+    pub(crate) fn render(&mut self) -> Result<()> {
+        self.renderer.render()?;
+        if let Some(latency_diagnostics) = self.latency_diagnostics.as_mut() {
+            latency_diagnostics.record_frame_rendered(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    fn log_selected_chart(&self) {
+        match self.chart_paths.get(self.selected_chart_index) {
+            Some(chart_path) => log::info!("Selected chart {}", chart_path),
+            None => log::info!("No charts found in {}", CHARTS_DIRECTORY),
+        }
+    }
+
+    fn log_rebinding_input(&self, input_index: usize) {
+        let input = RhythmControlInput::ALL[input_index];
+        let bindings = self
+            .rebinding_keybindings
+            .as_ref()
+            .map_or(&[][..], |keybindings| keybindings.bindings(input));
+        log::info!(
+            "Press keys or mouse buttons to add to {:?}, bound to {:?}. \
+             Enter continues, Backspace clears, Escape cancels",
+            input,
+            bindings
+        );
+    }
+
+    /// Adds the captured bindings to the input being rebound, taking them away from any other input.
+    fn update_rebinding(&mut self, input_index: usize) {
+        let bindings = self.input_handler.take_captured_bindings();
+        let Some(keybindings) = self.rebinding_keybindings.as_mut() else {
+            return;
+        };
+        if bindings.is_empty() {
+            return;
+        }
+
+        let input = RhythmControlInput::ALL[input_index];
+        for binding in bindings {
+            if self.settings.lane_layout().uses_key(&binding) {
+                log::error!(
+                    "Failed to bind {:?}: it is a key of the lane layout",
+                    binding
+                );
+                continue;
+            }
+            match keybindings.bind(input, binding) {
+                Ok(Some(previous_input)) => log::warn!(
+                    "{:?} was bound to {:?}, it is now bound to {:?} only",
+                    binding,
+                    previous_input,
+                    input
+                ),
+                Ok(None) => {}
+                Err(err) => log::error!("Failed to bind {:?}: {}", binding, err),
+            }
+        }
+        self.log_rebinding_input(input_index);
+    }
+
+    fn toggle_lane_layout(&mut self) {
+        let enabled = !self.settings.lane_layout().enabled;
+        if let Err(err) = self
+            .settings
+            .set_lane_layout_enabled(enabled, &self.keybindings)
+        {
+            log::error!("Failed to switch the lane layout: {}", err);
+            return;
+        }
+
+        self.input_handler.set_settings(&self.settings);
+        self.save_settings();
+        self.log_lane_layout();
+    }
+
+    fn save_settings(&self) {
+        if let Err(err) =
+            Settings::default_file_path().and_then(|file_path| self.settings.save(&file_path))
+        {
+            log::error!("Failed to save the settings: {}", err);
+        }
+    }
+
+    /// Picks the pack after the current one in the settings, charts with a pack of their own keep using it.
+    fn select_next_hitsound_pack(&mut self) {
+        let pack_names = match find_hitsound_packs() {
+            Ok(pack_names) if !pack_names.is_empty() => pack_names,
+            Ok(_) => {
+                log::warn!("No hitsound packs found");
+                return;
+            }
+            Err(err) => {
+                log::error!("Failed to find hitsound packs: {}", err);
+                return;
+            }
+        };
+        let next_index = pack_names
+            .iter()
+            .position(|pack_name| pack_name == self.settings.hitsound_pack())
+            .map_or(0, |index| (index + 1) % pack_names.len());
+        let pack_name = &pack_names[next_index];
+
+        self.use_hitsound_pack(pack_name);
+        self.settings.set_hitsound_pack(pack_name);
+        self.save_settings();
+        log::info!("Hitsound pack {} selected", pack_name);
+    }
+
+    /// Loads the pack unless it is the one in use, the previous pack is kept if loading fails.
+    fn use_hitsound_pack(&mut self, pack_name: &str) {
+        if self
+            .hitsound_pack
+            .as_ref()
+            .is_some_and(|hitsound_pack| hitsound_pack.pack_name() == pack_name)
+        {
+            return;
+        }
+
+        match HitsoundPack::load(&self.audio_system, pack_name) {
+            Ok(hitsound_pack) => self.hitsound_pack = Some(hitsound_pack),
+            Err(err) => log::error!("Failed to load the hitsound pack {}: {}", pack_name, err),
+        }
+    }
+
+    /// Previews which cells each key of the lane layout covers.
+    fn log_lane_layout(&self) {
+        let lane_layout = self.settings.lane_layout();
+        log::info!(
+            "Lane layout {}, {} keys over the cells: {}",
+            if lane_layout.enabled { "on" } else { "off" },
+            lane_layout.lane_count(),
+            lane_layout.preview()
+        );
+    }
+
+    /// Moves on to the next input, applies and saves the keybindings after the last one.
+    fn next_rebinding_input(&mut self, input_index: usize) {
+        if input_index + 1 < RhythmControlInput::ALL.len() {
+            self.transition(Scene::Rebinding {
+                input_index: input_index + 1,
+            });
+            return;
+        }
+
+        self.input_handler.end_binding_capture();
+        if let Some(keybindings) = self.rebinding_keybindings.take() {
+            self.input_handler.set_keybindings(&keybindings);
+            if let Err(err) =
+                Keybindings::default_file_path().and_then(|file_path| keybindings.save(&file_path))
+            {
+                log::error!("Failed to save the keybindings: {}", err);
+            }
+            self.keybindings = keybindings;
+        }
+        self.transition(Scene::SongSelect);
+    }
+
+    /// Creates the renderer and audio resources of the chart and starts the music.
+    fn load_chart(&mut self, chart_path: &str) -> Result<()> {
+        // A replay is played back with the modifiers it was recorded with.
+        let replay = self
+            .launch_options
+            .replay_path
+            .as_ref()
+            .map(|replay_path| Replay::load(replay_path))
+            .transpose()?;
+        let modifiers = replay
+            .as_ref()
+            .map_or(self.launch_options.modifiers, |replay| {
+                replay.settings.modifiers
+            });
+
+        let runtime_chart = parse_chart_file(chart_path, &modifiers)?;
+        let runner_speed_map = runtime_chart.create_runner_speed_map(RUNNER_SPEED);
+
+        // Create renderer resources based on the parsed chart.
+        self.renderer
+            .set_platform_objects(runtime_chart.create_platform_objects(&runner_speed_map))?;
+        self.renderer
+            .add_hit_objects(&runtime_chart.create_hit_objects(&runner_speed_map));
+        self.renderer
+            .set_hit_object_fade(runtime_chart.hit_object_fade());
+
+        let hitsound_pack = if runtime_chart.chart_info.hitsound_pack.is_empty() {
+            self.settings.hitsound_pack().to_string()
+        } else {
+            runtime_chart.chart_info.hitsound_pack.clone()
+        };
+        self.use_hitsound_pack(&hitsound_pack);
+
+        // Load chart music.
+        let music_index = self
+            .audio_system
+            .load_music_data(&runtime_chart.chart_info.music_file_path)?;
+
+        let autoplay = self
+            .launch_options
+            .autoplay
+            .then(|| Autoplay::new(runtime_chart.notes(), 0.0));
+        self.game_state.set_chart(runtime_chart);
+        if let Some(replay) = replay {
+            self.game_state.start_replay(replay)?;
+            log::info!("Playing back replay with modifiers {:?}", modifiers);
+        }
+
+        self.steering.reset();
+        // Events queued before the chart, e.g. releases outside of play, are not judged on it.
+        self.input_handler.reset();
+        self.session = Some(PlaySession {
+            runner_speed_map,
+            autoplay_used: autoplay.is_some(),
+            autoplay,
+        });
+
+        // Start the music, the game state's conductor keeps track of the current music position.
+        let sound_handle = self.audio_system.play_music(music_index)?;
+        self.game_state
+            .conductor_mut()
+            .set_clock(Box::new(AudioClock::new(sound_handle)));
+
+        Ok(())
+    }
+
+    fn unload_chart(&mut self) -> Result<()> {
+        self.game_state.conductor_mut().stop()?;
+        self.renderer.clear_game_objects()?;
+        self.audio_system.unload_music_data();
+        self.session = None;
+        if let Some(latency_diagnostics) = self.latency_diagnostics.as_mut() {
+            latency_diagnostics.report();
+            latency_diagnostics.clear();
+        }
+        // Events of the unloaded chart refer to notes that are gone.
+        self.game_events.try_iter().for_each(drop);
+
+        Ok(())
+    }
+
+    fn toggle_autoplay(&mut self) {
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        let current_music_position = self
+            .game_state
+            .conductor()
+            .get_current_music_position()
+            .unwrap_or(0.0);
+
+        if let Some(mut autoplay) = session.autoplay.take() {
+            for input in autoplay.release_all(current_music_position) {
+                self.game_state.handle_input(input);
+            }
+            log::info!("Autoplay off");
+        } else if let Some(chart) = self.game_state.chart() {
+            session.autoplay = Some(Autoplay::new(chart.notes(), current_music_position));
+            session.autoplay_used = true;
+            log::info!("Autoplay on");
+        }
+    }
+
+    fn update_playing(&mut self, frame_dt: f32) {
+        let Some(mut current_music_position) =
+            self.game_state.conductor().get_current_music_position()
+        else {
+            return;
+        };
+
+        // Events still queued, e.g. behind a release held back for debouncing, are judged at their own time in a
+        // later update, the game is not advanced past them meanwhile.
+        let live_events = self.input_handler.drain_events();
+        if let Some(queued_position) = self
+            .input_handler
+            .earliest_queued_instant()
+            .and_then(|instant| self.game_state.conductor().music_position_at(instant))
+        {
+            current_music_position = current_music_position.min(queued_position);
+        }
+
+        let mut inputs = Vec::new();
+        if let Some(autoplay) = self
+            .session
+            .as_mut()
+            .and_then(|session| session.autoplay.as_mut())
+        {
+            inputs.extend(autoplay.events_until(current_music_position));
+
+            // Steered through the same path as the player, who can take over from there.
+            let platform_extents = self.game_state.platform_extents(current_music_position);
+            if let Some(lateral_position) = Autoplay::steering_position(&platform_extents) {
+                self.steering.set_lateral_position(lateral_position);
+            }
+        }
+
+        if let Some(axis) = self.input_handler.sample_mouse_axis() {
+            self.steering.set_mouse_axis(axis);
+        }
+        if let Some(lateral_position) = self.input_handler.take_touch_steering() {
+            self.steering.set_lateral_position(lateral_position);
+        }
+
+        self.game_state.steer_runner(SteeringEvent {
+            time: current_music_position,
+            lateral_position: self.steering.update(frame_dt),
+        });
+
+        // Inputs are judged at the music position they were received at, kept in order and no later than the
+        // position the game is updated to.
+        let mut last_input_time = f32::MIN;
+        for chatter in self.input_handler.drain_debounced_chatter() {
+            if let Some(latency_diagnostics) = self.latency_diagnostics.as_mut() {
+                latency_diagnostics.record_debounced_chatter(chatter);
+            }
+        }
+
+        for timestamped_event in live_events {
+            let id = self
+                .latency_diagnostics
+                .as_mut()
+                .and_then(|latency_diagnostics| {
+                    latency_diagnostics.record_input(&timestamped_event)
+                });
+
+            let time = self
+                .game_state
+                .conductor()
+                .music_position_at(timestamped_event.timestamps.input_instant())
+                .unwrap_or(current_music_position)
+                .clamp(last_input_time, current_music_position);
+            last_input_time = time;
+
+            inputs.push(JudgementInput {
+                time,
+                input: timestamped_event.event.input,
+                pressed: timestamped_event.event.pressed,
+                cells: timestamped_event.cells,
+                id,
+            });
+        }
+
+        // Autoplay inputs land on the exact song time of their notes, they are merged with the live inputs in
+        // order. The sort is stable, inputs at the same time keep the order they were taken in.
+        inputs.sort_by(|a, b| a.time.total_cmp(&b.time));
+        for input in inputs {
+            self.game_state.handle_input(input);
+        }
+
+        self.game_state.update_until(current_music_position);
+        self.handle_game_events();
+
+        let runner_color = if self.game_state.is_runner_on_platform() {
+            RUNNER_ON_PLATFORM_COLOR
+        } else {
+            RUNNER_OFF_PLATFORM_COLOR
+        };
+        self.renderer.set_runner_marker(Some((
+            self.game_state.runner_lateral_position(),
+            runner_color,
+        )));
+    }
+
+    /// Presents judgements through the renderer and audio.
+    fn handle_game_events(&mut self) {
+        for event in self.game_events.try_iter() {
+            let mut hit_input_id = None;
+            let (hit, hitsound) = match event {
+                GameEvent::NoteHit {
+                    note_index,
+                    judgement,
+                    input_id,
+                } => {
+                    hit_input_id = input_id;
+                    self.renderer.hide_hit_object(note_index);
+                    let kind = self
+                        .game_state
+                        .chart()
+                        .and_then(|chart| chart.notes().get(note_index))
+                        .map_or(HitsoundKind::Tap, HitsoundKind::of_note);
+                    (Some((note_index, judgement)), Some((kind, judgement)))
+                }
+                GameEvent::NoteMissed { note_index } => {
+                    self.renderer
+                        .set_hit_object_color(note_index, MISSED_NOTE_COLOR);
+                    (None, Some((HitsoundKind::Miss, Judgement::Miss)))
+                }
+                GameEvent::HoldEnded {
+                    judgement: Judgement::Miss,
+                    ..
+                } => (None, Some((HitsoundKind::Miss, Judgement::Miss))),
+                GameEvent::HoldEnded {
+                    note_index,
+                    judgement,
+                } => (
+                    Some((note_index, judgement)),
+                    Some((HitsoundKind::HoldEnd, judgement)),
+                ),
+                GameEvent::RunnerLeftPlatform => {
+                    self.renderer
+                        .flash_judgement_line(RUNNER_OFF_PLATFORM_COLOR);
+                    (None, None)
+                }
+                GameEvent::RunnerReturnedToPlatform => (None, None),
+            };
+
+            if let Some((note_index, judgement)) = hit {
+                let color = judgement_color(judgement);
+                self.renderer.spawn_hit_effect(note_index, color);
+                self.renderer.flash_judgement_line(color);
+            }
+
+            let (Some((kind, judgement)), Some(hitsound_pack)) =
+                (hitsound, self.hitsound_pack.as_ref())
+            else {
+                continue;
+            };
+            match hitsound_pack.play(kind, judgement) {
+                Ok(true) => {
+                    if let (Some(input_id), Some(latency_diagnostics)) =
+                        (hit_input_id, self.latency_diagnostics.as_mut())
+                    {
+                        latency_diagnostics.record_sound_scheduled(input_id, Instant::now());
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => log::error!("Failed to play the hitsound: {}", err),
+            }
+        }
+    }
+
+    fn report_results(&self) {
+        let Some(results) = self.game_state.results() else {
+            return;
+        };
+
+        match results.to_json() {
+            Ok(json) => log::info!("Run results:\n{}", json),
+            Err(err) => log::error!("Failed to serialize the results: {}", err),
+        }
+
+        let autoplay_used = self
+            .session
+            .as_ref()
+            .map_or(false, |session| session.autoplay_used);
+        if !autoplay_used && !self.game_state.is_playing_replay() {
+            if let Err(err) = self.save_score(&results) {
+                log::error!("Failed to save the score: {}", err);
+            }
+        }
+
+        if let (Some(save_replay_path), Some(replay)) = (
+            &self.launch_options.save_replay_path,
+            self.game_state.replay(),
+        ) {
+            match replay.save(save_replay_path) {
+                Ok(()) => log::info!("Saved replay to {}", save_replay_path),
+                Err(err) => log::error!("Failed to save the replay: {}", err),
+            }
+        }
+    }
+
+    /// Adds the run to the local score database.
+    fn save_score(&self, results: &RunResults) -> Result<()> {
+        let chart_info = &self
+            .game_state
+            .chart()
+            .ok_or_else(|| anyhow!("No chart to save the score of"))?
+            .chart_info;
+
+        let mut score_database = ScoreDatabase::open_default()?;
+        if score_database.add_run(chart_info.chart_hash, &chart_info.difficulty, results) {
+            log::info!("New best {} score {}", results.scoring_rule, results.score);
+        }
+        score_database.save()
+    }
+}
+
+/// Whether the event ends a key press, mouse button press or touch.
+fn is_release(event: &WindowEvent) -> bool {
+    match event {
+        WindowEvent::KeyboardInput { event, .. } => event.state == ElementState::Released,
+        WindowEvent::MouseInput { state, .. } => *state == ElementState::Released,
+        WindowEvent::Touch(touch) => {
+            matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled)
+        }
+        _ => false,
+    }
+}
+
+/// Chart files of the song select, sorted by path.
+fn find_chart_files() -> Result<Vec<String>> {
+    let mut chart_paths = std::fs::read_dir(CHARTS_DIRECTORY)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == CHART_FILE_EXTENSION)
+        })
+        .filter_map(|path| path.to_str().map(String::from))
+        .collect::<Vec<_>>();
+    chart_paths.sort();
+
+    Ok(chart_paths)
+}
@@ -0,0 +1,144 @@
+/*!
+ * Headless simulation of a chart run, driven by a replay or autoplay on a manually stepped clock.
+ * Runs without a window, audio or GPU and prints the run's results as JSON, e.g. to regression test judgement
+ * rules and chart changes.
+ *
+ * Usage: chizumu-simulate <chart file> (--replay <replay file> | --autoplay) [--step <secs>]
+ */
+
+use anyhow::{anyhow, Result};
+
+use chizumu::chart::{modifiers::ChartModifiers, parse::parse_chart_file};
+use chizumu::game::{
+    autoplay::Autoplay,
+    clock::ManualClock,
+    replay::{Replay, SteeringEvent},
+    GameState,
+};
+
+/// Default simulation step in seconds.
+const DEFAULT_STEP: f32 = 1.0 / 240.0;
+/// Song time simulated after the end of the chart before giving up on the run ending.
+const END_MARGIN: f32 = 10.0;
+
+struct SimulationOptions {
+    chart_path: String,
+    replay_path: Option<String>,
+    autoplay: bool,
+    step: f32,
+}
+
+impl SimulationOptions {
+    fn from_args() -> Result<Self> {
+        let mut chart_path = None;
+        let mut replay_path = None;
+        let mut autoplay = false;
+        let mut step = DEFAULT_STEP;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for argument `{}`", arg))
+            };
+            match arg.as_str() {
+                "--replay" => replay_path = Some(value()?),
+                "--autoplay" => autoplay = true,
+                "--step" => step = value()?.parse()?,
+                _ if !arg.starts_with("--") && chart_path.is_none() => chart_path = Some(arg),
+                _ => return Err(anyhow!("Unknown argument `{}`", arg)),
+            }
+        }
+
+        if replay_path.is_some() == autoplay {
+            return Err(anyhow!(
+                "Exactly one of `--replay` or `--autoplay` is required"
+            ));
+        }
+        if !(step.is_finite() && step > 0.0) {
+            return Err(anyhow!("Simulation step has to be positive"));
+        }
+
+        Ok(Self {
+            chart_path: chart_path.ok_or_else(|| anyhow!("Missing chart file argument"))?,
+            replay_path,
+            autoplay,
+            step,
+        })
+    }
+}
+
+fn simulate(options: &SimulationOptions) -> Result<String> {
+    let replay = options
+        .replay_path
+        .as_ref()
+        .map(|replay_path| Replay::load(replay_path))
+        .transpose()?;
+    let modifiers = replay.as_ref().map_or(ChartModifiers::default(), |replay| {
+        replay.settings.modifiers
+    });
+
+    let runtime_chart = parse_chart_file(&options.chart_path, &modifiers)?;
+    let end_time = runtime_chart.end_offset() + END_MARGIN;
+
+    let mut game_state = GameState::new();
+    game_state.set_chart(runtime_chart);
+    if let Some(replay) = replay {
+        game_state.start_replay(replay)?;
+    }
+
+    let mut autoplay = options
+        .autoplay
+        .then(|| Autoplay::new(game_state.chart().unwrap().notes(), 0.0));
+
+    let clock = ManualClock::new(0.0);
+    game_state
+        .conductor_mut()
+        .set_clock(Box::new(clock.clone()));
+
+    let mut step_index = 0u32;
+    let mut time = 0.0;
+    while !game_state.is_run_ended() && time < end_time {
+        // Computed from the step index instead of accumulated so the time does not drift over long charts.
+        step_index += 1;
+        time = step_index as f32 * options.step;
+        clock.set_position(time);
+
+        // Autoplay inputs land on the exact song time of their notes, the same as in the game.
+        if let Some(autoplay) = autoplay.as_mut() {
+            for input in autoplay.events_until(time) {
+                game_state.handle_input(input);
+            }
+
+            let platform_extents = game_state.platform_extents(time);
+            if let Some(lateral_position) = Autoplay::steering_position(&platform_extents) {
+                game_state.steer_runner(SteeringEvent {
+                    time,
+                    lateral_position,
+                });
+            }
+        }
+
+        game_state.update();
+    }
+
+    if !game_state.is_run_ended() {
+        log::warn!("Run did not end within {}s of song time", end_time);
+    }
+
+    game_state
+        .results()
+        .ok_or_else(|| anyhow!("No chart is set to build results of"))?
+        .to_json()
+}
+
+fn main() -> Result<()> {
+    let env = env_logger::Env::default()
+        .filter_or("MY_LOG_LEVEL", "warn")
+        .write_style_or("MY_LOG_STYLE", "always");
+    env_logger::init_from_env(env);
+
+    let options = SimulationOptions::from_args()?;
+    println!("{}", simulate(&options)?);
+    Ok(())
+}